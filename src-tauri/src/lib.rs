@@ -1,8 +1,19 @@
-use serde::{Deserialize, Serialize};
-use std::process::{Command, Stdio};
-use std::sync::Mutex;
+use encoding_rs::Encoding;
+use regex::Regex;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 use std::io::{BufRead, BufReader, Read as IoRead};
+use tauri::ipc::Channel;
 use tauri::{AppHandle, Emitter, Manager, State};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Registry};
 use wait_timeout::ChildExt;
 
 // ---------------------------------------------------------------------------
@@ -14,24 +25,204 @@ pub struct AgentOutputEvent {
     pub id: String,
     pub data: String,
     pub stream: String,
+    /// `data` is an in-progress `\r` segment that the next event replaces.
+    pub partial: bool,
+    /// `data` is the start of a line that was over `max_line_bytes`, or for
+    /// the stderr event at the end of a run, only its last lines.
+    pub truncated: bool,
+}
+
+/// Several output lines coalesced into one event (see `RunOptions::batch_ms`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentOutputBatch {
+    pub id: String,
+    pub stream: String,
+    pub lines: Vec<String>,
+}
+
+/// Emitted whenever a process is started, whichever command started it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentSpawnedEvent {
+    pub id: String,
+    pub pid: u32,
+    pub command: String,
+    pub args: Vec<String>,
+    /// Absolute path of the launched program as found on PATH, to tell
+    /// multiple installs of the same tool apart.
+    pub exe_path: Option<String>,
+    /// Known agent this command matches (a discovered agent's `id`), for
+    /// theming and update support.
+    pub signature_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentDoneEvent {
     pub id: String,
     pub code: Option<i32>,
+    /// The exit counts as a normal finish: stopped by the user, a clean
+    /// interrupt, or a code in `success_codes`. Timeouts, emit failures and
+    /// crashes are never a success, whatever the exit code.
+    pub success: bool,
+    /// Ended by Ctrl+C / SIGINT (exit code 130 or the signal itself).
+    pub interrupted: bool,
+    /// Why the agent was stopped; `None` when it exited on its own.
+    pub stop_reason: Option<StopReason>,
+}
+
+/// What stopped a run before it exited on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// A terminating signal (INT, TERM, KILL, HUP, QUIT) was sent with
+    /// `send_signal`.
+    Manual,
+    /// `idle_timeout_ms` ran out with `idle_action` set to stop.
+    IdleTimeout,
+    /// `max_runtime_ms` ran out.
+    MaxRuntime,
+    /// Output could no longer be delivered to the frontend.
+    EmitFailed,
+    /// Killed by a signal nobody in AgentHub sent (Unix only).
+    Crashed,
+}
+
+/// Error returned from every command.
+///
+/// Serialized as `{ kind, message }` so the frontend can branch on the stable
+/// `kind` discriminant and still show a readable message.
+#[derive(Debug, Clone)]
+pub enum AgentError {
+    NotFound(String),
+    AlreadyRunning(String),
+    SpawnFailed(String),
+    UpdateUnsupported(String),
+    InvalidPattern(String),
+    IoError(String),
+    UnsupportedSignal(String),
+    SignalFailed(String),
+    InvalidInput(String),
+}
+
+impl AgentError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AgentError::NotFound(_) => "not_found",
+            AgentError::AlreadyRunning(_) => "already_running",
+            AgentError::SpawnFailed(_) => "spawn_failed",
+            AgentError::UpdateUnsupported(_) => "update_unsupported",
+            AgentError::InvalidPattern(_) => "invalid_pattern",
+            AgentError::IoError(_) => "io_error",
+            AgentError::UnsupportedSignal(_) => "unsupported_signal",
+            AgentError::SignalFailed(_) => "signal_failed",
+            AgentError::InvalidInput(_) => "invalid_input",
+        }
+    }
+}
+
+impl std::fmt::Display for AgentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgentError::NotFound(id) => write!(f, "Agent '{}' nebyl nalezen", id),
+            AgentError::AlreadyRunning(id) => write!(f, "Agent '{}' právě zpracovává zprávu", id),
+            AgentError::SpawnFailed(e) => write!(f, "Chyba při spouštění: {}", e),
+            AgentError::UpdateUnsupported(cmd) => {
+                write!(f, "Agent '{}' není nainstalovaný přes npm, brew, cargo ani pip", cmd)
+            }
+            AgentError::InvalidPattern(e) => write!(f, "Neplatný vzor: {}", e),
+            AgentError::IoError(e) => write!(f, "Chyba při práci se souborem: {}", e),
+            AgentError::UnsupportedSignal(name) => write!(f, "Signál '{}' není podporován", name),
+            AgentError::SignalFailed(e) => write!(f, "Nepodařilo se odeslat signál: {}", e),
+            AgentError::InvalidInput(e) => write!(f, "Neplatný vstup: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+impl Serialize for AgentError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("AgentError", 2)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
 }
 
 /// Tracks which agents are currently processing a message.
 pub struct AgentState {
     pub busy: Mutex<std::collections::HashSet<String>>,
+    /// Result of the last `discover_agents` scan.
+    pub discovered: Mutex<Vec<DiscoveredAgent>>,
+    /// Per-agent output channels registered via `subscribe_agent_output`.
+    pub subscribers: Mutex<Subscribers>,
+    /// Child handles of in-flight runs, for `send_signal` and metrics.
+    running: Mutex<std::collections::HashMap<String, Arc<RunControl>>>,
+    /// stdout of finished `capture_binary` runs, until fetched.
+    binary: Mutex<std::collections::HashMap<String, Vec<u8>>>,
+    /// Kept between `get_total_resources` calls so CPU usage has a baseline.
+    system: Mutex<sysinfo::System>,
 }
 
+type Subscribers = std::collections::HashMap<String, Vec<Channel<AgentOutputEvent>>>;
+
 impl Default for AgentState {
     fn default() -> Self {
         Self {
             busy: Mutex::new(std::collections::HashSet::new()),
+            discovered: Mutex::new(Vec::new()),
+            subscribers: Mutex::new(std::collections::HashMap::new()),
+            running: Mutex::new(std::collections::HashMap::new()),
+            binary: Mutex::new(std::collections::HashMap::new()),
+            system: Mutex::new(sysinfo::System::new()),
+        }
+    }
+}
+
+/// Lock `mutex`, reclaiming it if a thread panicked while holding it. The
+/// state behind these locks is plain data, so a poisoned guard is still
+/// consistent.
+fn lock_recover<'a, T>(mutex: &'a Mutex<T>, name: &str) -> MutexGuard<'a, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        tracing::warn!(lock = name, "lock was poisoned, recovering");
+        poisoned.into_inner()
+    })
+}
+
+impl AgentState {
+    pub fn lock_busy(&self) -> MutexGuard<'_, std::collections::HashSet<String>> {
+        lock_recover(&self.busy, "busy")
+    }
+
+    pub fn lock_discovered(&self) -> MutexGuard<'_, Vec<DiscoveredAgent>> {
+        lock_recover(&self.discovered, "discovered")
+    }
+
+    pub fn lock_subscribers(&self) -> MutexGuard<'_, Subscribers> {
+        lock_recover(&self.subscribers, "subscribers")
+    }
+
+    fn lock_binary(&self) -> MutexGuard<'_, std::collections::HashMap<String, Vec<u8>>> {
+        lock_recover(&self.binary, "binary")
+    }
+
+    fn lock_system(&self) -> MutexGuard<'_, sysinfo::System> {
+        lock_recover(&self.system, "system")
+    }
+
+    fn lock_running(&self) -> MutexGuard<'_, std::collections::HashMap<String, Arc<RunControl>>> {
+        lock_recover(&self.running, "running")
+    }
+
+    /// Mark `id` as busy, failing if a run is already in progress.
+    fn claim(&self, id: &str) -> Result<(), AgentError> {
+        if !self.lock_busy().insert(id.to_string()) {
+            return Err(AgentError::AlreadyRunning(id.to_string()));
         }
+        Ok(())
+    }
+
+    fn release(&self, id: &str) {
+        self.lock_busy().remove(id);
     }
 }
 
@@ -39,291 +230,3224 @@ impl Default for AgentState {
 // One-shot agent execution
 // ---------------------------------------------------------------------------
 
-/// Run a one-shot message through a CLI agent.
-/// Spawns `<command> -p "<message>"`, streams output back via events.
-#[tauri::command]
-fn run_agent(
-    id: String,
-    command: String,
-    message: String,
-    state: State<'_, AgentState>,
-    app: AppHandle,
-) -> Result<(), String> {
-    // Check if already processing
-    {
-        let mut busy = state.busy.lock().map_err(|e| e.to_string())?;
-        if busy.contains(&id) {
-            return Err(format!("Agent '{}' právě zpracovává zprávu", id));
-        }
-        busy.insert(id.clone());
+/// Optional per-run settings for `run_agent`. Every field defaults to the
+/// plain behaviour, so the frontend can omit the whole object.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RunOptions {
+    /// Buffer stdout for up to this many milliseconds and emit it as one
+    /// `agent-output-batch` event. `None` keeps one event per line.
+    pub batch_ms: Option<u64>,
+    /// Flush a batch early once it holds this many lines.
+    pub batch_lines: Option<usize>,
+    /// Treat the agent as idle after this long without stdout/stderr output.
+    pub idle_timeout_ms: Option<u64>,
+    /// What to do once `idle_timeout_ms` elapses.
+    pub idle_action: IdleAction,
+    /// Regexes checked against each stderr line; a match emits
+    /// `agent-error-detected` without affecting the run.
+    pub error_patterns: Vec<String>,
+    /// File streamed into the child's stdin, which is closed at EOF.
+    pub stdin_file: Option<String>,
+    /// Remove ANSI/VT escape sequences (colors, cursor moves) from output
+    /// before emitting it. Off by default so terminal views get raw text.
+    pub strip_ansi: bool,
+    /// Scheduling priority of the agent process.
+    pub priority: Priority,
+    /// Directories put in front of the inherited PATH for this run only.
+    pub path_prepend: Vec<String>,
+    /// Also emit `agent-json` for stdout lines that are a JSON object or
+    /// array. The raw line is still sent as normal output.
+    pub parse_json_lines: bool,
+    /// Send stderr through the stdout pipe so one reader sees both in their
+    /// original order. Every line is then reported as `stdout`. There is no
+    /// separate stderr to check, so `error_patterns` and
+    /// `stderr_flood_bytes` are refused alongside it.
+    pub merge_stderr: bool,
+    /// Cut lines off after this many bytes (default 1 MiB); events for
+    /// cut-off lines carry `truncated: true`.
+    pub max_line_bytes: Option<usize>,
+    /// Collect stdout as raw bytes for `get_agent_binary` instead of emitting
+    /// it as text, for agents whose output is a file. stderr still streams,
+    /// so this can't be combined with `merge_stderr`.
+    pub capture_binary: bool,
+    /// Stop the agent once it has run this long, even while it's producing
+    /// output. It gets a few seconds to exit before being killed.
+    pub max_runtime_ms: Option<u64>,
+    /// Run started once this one exits, with `AGENT_HUB_EXITED_ID` and
+    /// `AGENT_HUB_EXIT_CODE` set in its environment.
+    pub on_exit: Option<Box<RunSpec>>,
+    /// Emit `agent-stderr-flood` once stderr passes this many bytes
+    /// (default 1 MiB), which usually means a crash loop or bad config.
+    pub stderr_flood_bytes: Option<u64>,
+    /// Drop stdout lines beyond this many per second. Each second starts a
+    /// fresh allowance, so a flooding agent still shows periodic output.
+    pub max_lines_per_sec: Option<u32>,
+    /// Exit codes that count as success in `agent-done`. Empty means `[0]`.
+    pub success_codes: Vec<i32>,
+    /// Unix: launch through `$SHELL -lc` so the login profile is sourced
+    /// and PATH/env match the user's terminal. `agent-spawned` then reports
+    /// the shell as the command, but `exe_path` and `signature_id` are still
+    /// the agent's, looked up through the login shell. Ignored on Windows,
+    /// where processes already inherit the user environment.
+    pub login_shell: bool,
+    /// Encoding label of the agent's output, e.g. `windows-1250` for a
+    /// console in the Czech codepage. Defaults to UTF-8; only encodings
+    /// that keep ASCII line breaks are accepted.
+    pub encoding: Option<String>,
+}
+
+impl RunOptions {
+    fn max_line(&self) -> usize {
+        self.max_line_bytes.unwrap_or(DEFAULT_MAX_LINE_BYTES).max(1)
     }
 
-    let app_handle = app.clone();
-    let agent_id = id.clone();
+    fn encoding(&self) -> &'static Encoding {
+        self.encoding
+            .as_deref()
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8)
+    }
 
-    std::thread::spawn(move || {
-        // Build command: cmd /c <command> -p "<message>"
-        #[cfg(target_os = "windows")]
-        let child = Command::new("cmd")
-            .args(["/c", &command, "-p", &message])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn();
+    /// Validate the options and compile anything the run thread needs.
+    fn prepare(self) -> Result<PreparedRun, AgentError> {
+        // stderr text would end up inside the captured file
+        if self.capture_binary && self.merge_stderr {
+            return Err(AgentError::InvalidInput("capture_binary nelze kombinovat s merge_stderr".to_string()));
+        }
+        if self.merge_stderr && (!self.error_patterns.is_empty() || self.stderr_flood_bytes.is_some()) {
+            return Err(AgentError::InvalidInput(
+                "error_patterns a stderr_flood_bytes nelze kombinovat s merge_stderr".to_string(),
+            ));
+        }
+        let error_patterns = self
+            .error_patterns
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| AgentError::InvalidPattern(e.to_string())))
+            .collect::<Result<_, _>>()?;
+        // A directory containing the PATH separator can't be joined in
+        std::env::join_paths(&self.path_prepend)
+            .map_err(|e| AgentError::InvalidInput(format!("path_prepend: {}", e)))?;
+        // Lines are split on raw `\n`/`\r` bytes, which UTF-16 doesn't keep
+        if let Some(label) = &self.encoding {
+            match Encoding::for_label(label.as_bytes()) {
+                Some(encoding) if encoding.is_ascii_compatible() => {}
+                _ => return Err(AgentError::InvalidInput(format!("nepodporované kódování '{}'", label))),
+            }
+        }
+        // Open up front so a bad path fails the command instead of the run
+        let stdin_file = self
+            .stdin_file
+            .as_ref()
+            .map(|path| {
+                std::fs::File::open(path).map_err(|e| AgentError::IoError(format!("{}: {}", path, e)))
+            })
+            .transpose()?;
+        Ok(PreparedRun {
+            options: self,
+            error_patterns,
+            stdin_file,
+        })
+    }
+}
 
-        #[cfg(not(target_os = "windows"))]
-        let child = Command::new(&command)
-            .args(["-p", &message])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn();
-
-        match child {
-            Ok(mut child) => {
-                // Read stdout line by line and stream to frontend
-                if let Some(stdout) = child.stdout.take() {
-                    let reader = BufReader::new(stdout);
-                    for line in reader.lines() {
-                        if let Ok(text) = line {
-                            let _ = app_handle.emit("agent-output", &AgentOutputEvent {
-                                id: agent_id.clone(),
-                                data: text,
-                                stream: "stdout".to_string(),
-                            });
-                        }
-                    }
-                }
+/// `RunOptions` after validation.
+struct PreparedRun {
+    options: RunOptions,
+    error_patterns: Vec<Regex>,
+    stdin_file: Option<std::fs::File>,
+}
 
-                // Collect stderr
-                if let Some(mut stderr) = child.stderr.take() {
-                    let mut err_text = String::new();
-                    let _ = stderr.read_to_string(&mut err_text);
-                    if !err_text.trim().is_empty() {
-                        let _ = app_handle.emit("agent-output", &AgentOutputEvent {
-                            id: agent_id.clone(),
-                            data: err_text.trim().to_string(),
-                            stream: "stderr".to_string(),
-                        });
-                    }
-                }
+/// Process priority: niceness on Unix, priority class on Windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    /// Raising priority needs privileges on Unix; without them the agent
+    /// simply runs at normal priority.
+    High,
+}
 
-                // Wait for exit
-                let code = child.wait().ok().and_then(|s| s.code());
-                let _ = app_handle.emit("agent-done", &AgentDoneEvent {
-                    id: agent_id.clone(),
-                    code,
-                });
-            }
-            Err(e) => {
-                let _ = app_handle.emit("agent-output", &AgentOutputEvent {
-                    id: agent_id.clone(),
-                    data: format!("Chyba při spouštění: {}", e),
-                    stream: "stderr".to_string(),
-                });
-                let _ = app_handle.emit("agent-done", &AgentDoneEvent {
-                    id: agent_id.clone(),
-                    code: Some(-1),
-                });
-            }
+impl Priority {
+    #[cfg(unix)]
+    fn niceness(self) -> libc::c_int {
+        match self {
+            Priority::Low => 10,
+            Priority::Normal => 0,
+            Priority::High => -5,
         }
+    }
 
-        // Mark as no longer busy
-        {
-            let state_ref = app_handle.state::<AgentState>();
-            let mut busy = state_ref.busy.lock().unwrap();
-            busy.remove(&agent_id);
+    #[cfg(target_os = "windows")]
+    fn creation_flags(self) -> u32 {
+        const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+        const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x0000_8000;
+        match self {
+            Priority::Low => BELOW_NORMAL_PRIORITY_CLASS,
+            Priority::Normal => 0,
+            Priority::High => ABOVE_NORMAL_PRIORITY_CLASS,
         }
-    });
+    }
+}
 
-    Ok(())
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentJsonEvent {
+    pub id: String,
+    pub value: serde_json::Value,
 }
 
-/// Check if an agent is currently busy processing.
-#[tauri::command]
-fn is_agent_busy(
-    id: String,
-    state: State<'_, AgentState>,
-) -> Result<bool, String> {
-    let busy = state.busy.lock().map_err(|e| e.to_string())?;
-    Ok(busy.contains(&id))
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentErrorDetectedEvent {
+    pub id: String,
+    pub line: String,
+    pub pattern: String,
 }
 
-// ---------------------------------------------------------------------------
-// Agent discovery - real system scan
-// ---------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleAction {
+    /// Emit `agent-idle` and keep running.
+    #[default]
+    Notify,
+    /// Emit `agent-idle` and kill the process.
+    Stop,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DiscoveredAgent {
+pub struct AgentIdleEvent {
     pub id: String,
-    pub name: String,
-    pub short_name: String,
-    pub command: String,
-    pub path: String,
-    pub color: String,
-    pub version: String,
-    pub available: bool,
+    pub idle_ms: u64,
+    /// The agent and everything it started were killed.
+    pub stopped: bool,
 }
 
-struct AgentSignature {
-    command: &'static str,
-    name: &'static str,
-    short_name: &'static str,
-    color: &'static str,
-    npm_package: &'static str,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStderrFloodEvent {
+    pub id: String,
+    pub bytes: u64,
 }
 
-fn agent_signatures() -> Vec<AgentSignature> {
-    vec![
-        AgentSignature { command: "claude",   name: "Claude Code",  short_name: "CC", color: "#00FF64", npm_package: "@anthropic-ai/claude-code" },
-        AgentSignature { command: "codex",    name: "Codex CLI",    short_name: "CX", color: "#3B82F6", npm_package: "@openai/codex" },
-        AgentSignature { command: "gemini",   name: "Gemini CLI",   short_name: "GM", color: "#FFB800", npm_package: "" },
-        AgentSignature { command: "aider",    name: "Aider",        short_name: "AI", color: "#9333EA", npm_package: "" },
-        AgentSignature { command: "cody",     name: "Cody CLI",     short_name: "CD", color: "#FF5733", npm_package: "" },
-        AgentSignature { command: "cursor",   name: "Cursor Agent", short_name: "CR", color: "#7C3AED", npm_package: "" },
-        AgentSignature { command: "amp",      name: "Amp",          short_name: "AM", color: "#F59E0B", npm_package: "" },
-    ]
-}
+const DEFAULT_STDERR_FLOOD_BYTES: u64 = 1024 * 1024;
 
-fn find_on_path(cmd: &str) -> Option<String> {
-    #[cfg(target_os = "windows")]
-    let output = Command::new("where")
-        .arg(cmd)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output();
+/// How much of stderr is kept for the event sent when the run ends. Older
+/// lines are dropped first, since the last ones usually explain the exit.
+const STDERR_TAIL_BYTES: usize = 64 * 1024;
 
-    #[cfg(not(target_os = "windows"))]
-    let output = Command::new("which")
-        .arg(cmd)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output();
+/// The last `limit` bytes worth of stderr lines.
+struct StderrTail {
+    lines: std::collections::VecDeque<String>,
+    bytes: usize,
+    limit: usize,
+    dropped: bool,
+}
 
-    match output {
-        Ok(o) if o.status.success() => {
-            let path = String::from_utf8_lossy(&o.stdout)
-                .lines()
-                .next()
-                .unwrap_or("")
-                .trim()
-                .to_string();
-            if path.is_empty() { None } else { Some(path) }
+impl StderrTail {
+    fn new(limit: usize) -> Self {
+        Self {
+            lines: std::collections::VecDeque::new(),
+            bytes: 0,
+            limit,
+            dropped: false,
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        self.bytes += line.len();
+        self.lines.push_back(line);
+        while self.bytes > self.limit {
+            let Some(oldest) = self.lines.pop_front() else {
+                break;
+            };
+            self.bytes -= oldest.len();
+            self.dropped = true;
         }
-        _ => None,
     }
+
+    /// The kept lines and whether any were dropped.
+    fn finish(self) -> (String, bool) {
+        (Vec::from(self.lines).join("\n"), self.dropped)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentTimeoutEvent {
+    pub id: String,
+    pub runtime_ms: u64,
 }
 
-fn get_version(cmd: &str) -> String {
-    use std::time::Duration;
+/// How long a stopped agent may take to exit before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(3);
+
+/// Consecutive output emits that may fail (e.g. to serialize) before the
+/// agent is killed instead of being read forever.
+const EMIT_FAILURE_LIMIT: u32 = 50;
 
+/// How often a run checks that a window is still open to receive output.
+const WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A running child shared between the thread that waits on it and the
+/// watchers that may need to stop it.
+///
+/// Stopping reaches the whole process tree: on Unix the child leads its own
+/// process group, on Windows it is put in a job object. Otherwise killing
+/// the `cmd /c` or `sh -c` wrapper would leave the agent running with the
+/// output pipe open.
+struct RunControl {
+    child: Mutex<Child>,
+    pid: u32,
+    last_activity: Mutex<Instant>,
+    finished: AtomicBool,
+    metrics: Mutex<MetricsTracker>,
+    emit_failures: AtomicU32,
+    windows_checked: Mutex<Instant>,
+    stdout_reading: AtomicBool,
+    stderr_reading: AtomicBool,
+    stop_reason: Mutex<Option<StopReason>>,
     #[cfg(target_os = "windows")]
-    let child = Command::new("cmd")
-        .args(["/c", cmd, "--version"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
+    job: Option<std::os::windows::io::OwnedHandle>,
+}
 
-    #[cfg(not(target_os = "windows"))]
-    let child = Command::new(cmd)
-        .arg("--version")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
+/// Put `child` in a new job object so its whole tree can be terminated.
+/// Processes it starts afterwards join the job too; `None` if the job
+/// couldn't be set up, in which case only the child itself is killed. The
+/// child is spawned suspended, so it can't start anything before this.
+#[cfg(target_os = "windows")]
+fn assign_job(child: &Child) -> Option<std::os::windows::io::OwnedHandle> {
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+    // SAFETY: an unnamed job with default security; the returned handle
+    // is owned (and closed) by the OwnedHandle from here on
+    let job = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+    if job.is_null() {
+        return None;
+    }
+    let job = unsafe { OwnedHandle::from_raw_handle(job) };
+    // SAFETY: both handles are valid for the duration of the call
+    let assigned = unsafe { AssignProcessToJobObject(job.as_raw_handle(), child.as_raw_handle()) } != 0;
+    assigned.then_some(job)
+}
 
-    match child {
-        Ok(mut child) => {
-            match child.wait_timeout(Duration::from_secs(5)) {
-                Ok(Some(_)) => {
-                    let stdout = child.stdout.take()
-                        .map(|s| {
-                            let mut buf = String::new();
-                            BufReader::new(s).read_line(&mut buf).ok();
-                            buf.trim().to_string()
-                        })
-                        .unwrap_or_default();
-                    if stdout.is_empty() {
-                        child.stderr.take()
-                            .map(|s| {
-                                let mut buf = String::new();
-                                BufReader::new(s).read_line(&mut buf).ok();
-                                buf.trim().to_string()
-                            })
-                            .unwrap_or_default()
-                    } else {
-                        stdout
-                    }
-                }
-                _ => {
-                    let _ = child.kill();
-                    String::new()
+/// Start a child spawned with `CREATE_SUSPENDED`. std doesn't hand out the
+/// main thread's handle, so the child's threads are looked up by owner pid.
+/// Returns whether any thread was resumed.
+#[cfg(target_os = "windows")]
+fn resume_child(child: &Child) -> bool {
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, THREAD_SUSPEND_RESUME};
+    // SAFETY: a snapshot of all threads, owned by the OwnedHandle below
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        return false;
+    }
+    let snapshot = unsafe { OwnedHandle::from_raw_handle(snapshot) };
+    // SAFETY: THREADENTRY32 is plain data; the API only needs dwSize set
+    let mut entry: THREADENTRY32 = unsafe { std::mem::zeroed() };
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+    let mut resumed = false;
+    // SAFETY: the snapshot handle and `entry` outlive every call
+    let mut more = unsafe { Thread32First(snapshot.as_raw_handle(), &mut entry) } != 0;
+    while more {
+        if entry.th32OwnerProcessID == child.id() {
+            // SAFETY: the thread handle is closed right after resuming it
+            unsafe {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if !thread.is_null() {
+                    resumed |= ResumeThread(thread) != u32::MAX;
+                    CloseHandle(thread);
                 }
             }
         }
-        Err(_) => String::new(),
+        more = unsafe { Thread32Next(snapshot.as_raw_handle(), &mut entry) } != 0;
     }
+    resumed
 }
 
-fn scan_npm_global() -> Vec<(String, String)> {
-    let output = Command::new("npm")
-        .args(["list", "-g", "--depth=0", "--json"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output();
+/// Marks a reader as running for as long as it is held, so the flag also
+/// drops if the reader panics.
+struct ReaderFlag<'a>(&'a AtomicBool);
 
-    let mut found = Vec::new();
-    if let Ok(o) = output {
-        let text = String::from_utf8_lossy(&o.stdout);
-        for sig in agent_signatures() {
-            if !sig.npm_package.is_empty() && text.contains(sig.npm_package) {
-                found.push((sig.npm_package.to_string(), sig.command.to_string()));
-            }
-        }
+impl<'a> ReaderFlag<'a> {
+    fn raise(flag: &'a AtomicBool) -> Self {
+        flag.store(true, Ordering::Relaxed);
+        ReaderFlag(flag)
     }
-    found
 }
 
-#[tauri::command]
-fn discover_agents() -> Vec<DiscoveredAgent> {
-    let signatures = agent_signatures();
-    let mut found: Vec<DiscoveredAgent> = Vec::new();
+impl Drop for ReaderFlag<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
 
-    // Scan npm global
-    let _npm_agents = scan_npm_global();
+/// Output throughput of a running agent, from `get_agent_metrics`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutputMetrics {
+    pub total_lines: u64,
+    pub total_bytes: u64,
+    /// Exponential moving averages over one-second samples.
+    pub lines_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// Lines dropped by `max_lines_per_sec`.
+    pub rate_dropped: u64,
+}
 
-    // Scan PATH + verify version
-    for sig in &signatures {
-        if let Some(path) = find_on_path(sig.command) {
-            let version = get_version(sig.command);
+/// Weight of the newest sample in the moving averages.
+const METRICS_ALPHA: f64 = 0.3;
 
-            found.push(DiscoveredAgent {
-                id: sig.command.to_string(),
-                name: sig.name.to_string(),
-                short_name: sig.short_name.to_string(),
-                command: sig.command.to_string(),
-                path,
-                color: sig.color.to_string(),
-                version,
-                available: true,
-            });
+struct MetricsTracker {
+    metrics: OutputMetrics,
+    sample_start: Instant,
+    sample_lines: u64,
+    sample_bytes: u64,
+}
+
+impl MetricsTracker {
+    fn new() -> Self {
+        Self {
+            metrics: OutputMetrics::default(),
+            sample_start: Instant::now(),
+            sample_lines: 0,
+            sample_bytes: 0,
         }
     }
 
-    found
+    /// Fold the current sample into the averages once it spans a second.
+    fn roll(&mut self) {
+        let secs = self.sample_start.elapsed().as_secs_f64();
+        if secs < 1.0 {
+            return;
+        }
+        let m = &mut self.metrics;
+        let ema = |avg: f64, count: u64| METRICS_ALPHA * (count as f64 / secs) + (1.0 - METRICS_ALPHA) * avg;
+        m.lines_per_sec = ema(m.lines_per_sec, self.sample_lines);
+        m.bytes_per_sec = ema(m.bytes_per_sec, self.sample_bytes);
+        self.sample_start = Instant::now();
+        self.sample_lines = 0;
+        self.sample_bytes = 0;
+    }
+
+    fn record(&mut self, chunk: &OutputChunk) {
+        self.roll();
+        let lines = u64::from(!chunk.partial);
+        let bytes = chunk.text.len() as u64;
+        self.metrics.total_lines += lines;
+        self.metrics.total_bytes += bytes;
+        self.sample_lines += lines;
+        self.sample_bytes += bytes;
+    }
+
+    fn snapshot(&mut self) -> OutputMetrics {
+        self.roll();
+        self.metrics.clone()
+    }
 }
 
-// ---------------------------------------------------------------------------
-// Entry point
-// ---------------------------------------------------------------------------
+impl RunControl {
+    fn new(child: Child) -> Self {
+        #[cfg(target_os = "windows")]
+        let job = assign_job(&child);
+        // Only now may the child run; one that can't be resumed would hang
+        // the run forever
+        #[cfg(target_os = "windows")]
+        let child = {
+            let mut child = child;
+            if !resume_child(&child) {
+                tracing::warn!(pid = child.id(), "could not resume suspended agent");
+                let _ = child.kill();
+            }
+            child
+        };
+        Self {
+            #[cfg(target_os = "windows")]
+            job,
+            pid: child.id(),
+            child: Mutex::new(child),
+            last_activity: Mutex::new(Instant::now()),
+            finished: AtomicBool::new(false),
+            metrics: Mutex::new(MetricsTracker::new()),
+            emit_failures: AtomicU32::new(0),
+            windows_checked: Mutex::new(Instant::now()),
+            stdout_reading: AtomicBool::new(false),
+            stderr_reading: AtomicBool::new(false),
+            stop_reason: Mutex::new(None),
+        }
+    }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .manage(AgentState::default())
-        .invoke_handler(tauri::generate_handler![
-            run_agent,
-            is_agent_busy,
-            discover_agents,
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+    /// Record stdout/stderr activity.
+    fn touch(&self) {
+        *lock_recover(&self.last_activity, "activity") = Instant::now();
+    }
+
+    /// Record a chunk of stdout/stderr as activity and for the metrics.
+    fn record(&self, chunk: &OutputChunk) {
+        self.touch();
+        lock_recover(&self.metrics, "metrics").record(chunk);
+    }
+
+    fn note_dropped(&self) {
+        lock_recover(&self.metrics, "metrics").metrics.rate_dropped += 1;
+    }
+
+    /// Track whether an output emit reached the frontend. Returns false once
+    /// no window is left to receive output or `EMIT_FAILURE_LIMIT` emits in
+    /// a row have failed, after killing the agent so its pipes close and the
+    /// reader threads wind down. `emit` succeeds when there are no webviews
+    /// at all, so that case is checked directly, at most once per
+    /// `WINDOW_CHECK_INTERVAL`.
+    fn note_emit(&self, app: &AppHandle, delivered: bool) -> bool {
+        if self.window_check_due() && app.webview_windows().is_empty() {
+            tracing::warn!(pid = self.pid, "no window left to receive output, killing agent");
+            self.mark_stopped(StopReason::EmitFailed);
+            self.kill();
+            return false;
+        }
+        if delivered {
+            self.emit_failures.store(0, Ordering::Relaxed);
+            return true;
+        }
+        let failures = self.emit_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures == EMIT_FAILURE_LIMIT {
+            tracing::warn!(pid = self.pid, failures, "output emits keep failing, killing agent");
+            self.mark_stopped(StopReason::EmitFailed);
+            self.kill();
+        }
+        failures < EMIT_FAILURE_LIMIT
+    }
+
+    fn window_check_due(&self) -> bool {
+        let mut checked = lock_recover(&self.windows_checked, "window check");
+        if checked.elapsed() < WINDOW_CHECK_INTERVAL {
+            return false;
+        }
+        *checked = Instant::now();
+        true
+    }
+
+    /// Record why the run is being stopped. The first reason wins, so a
+    /// watcher killing an agent that is already shutting down doesn't
+    /// overwrite it.
+    fn mark_stopped(&self, reason: StopReason) {
+        lock_recover(&self.stop_reason, "stop reason").get_or_insert(reason);
+    }
+
+    fn metrics(&self) -> OutputMetrics {
+        lock_recover(&self.metrics, "metrics").snapshot()
+    }
+
+    fn idle_for(&self) -> Duration {
+        lock_recover(&self.last_activity, "activity").elapsed()
+    }
+
+    /// Kill the child and everything it started. Returns whether the whole
+    /// tree could be killed, not just the direct child.
+    fn kill(&self) -> bool {
+        let mut child = lock_recover(&self.child, "child");
+        let mut tree_killed = false;
+        // Once the run is finished the group is gone and its id may be
+        // reused
+        #[cfg(unix)]
+        if !self.is_finished() {
+            let group = nix::unistd::Pid::from_raw(self.pid as i32);
+            tree_killed = nix::sys::signal::killpg(group, nix::sys::signal::Signal::SIGKILL).is_ok();
+        }
+        #[cfg(target_os = "windows")]
+        if let Some(job) = &self.job {
+            use std::os::windows::io::AsRawHandle;
+            // SAFETY: the job handle stays open for as long as `self` lives
+            tree_killed = unsafe {
+                windows_sys::Win32::System::JobObjects::TerminateJobObject(job.as_raw_handle(), 1)
+            } != 0;
+        }
+        let _ = child.kill();
+        tree_killed
+    }
+
+    /// Wait for the child to exit. The lock is only held for short slices so
+    /// watchers can still kill it in between.
+    fn wait(&self) -> Option<std::process::ExitStatus> {
+        loop {
+            match lock_recover(&self.child, "child").wait_timeout(Duration::from_millis(50)) {
+                Ok(Some(status)) => return Some(status),
+                Ok(None) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Ask the child to exit with SIGTERM and kill it if it's still running
+    /// after `grace`. Windows has no polite equivalent, so it is killed
+    /// straight away there.
+    fn terminate(&self, grace: Duration) {
+        #[cfg(unix)]
+        if self.signal(nix::sys::signal::Signal::SIGTERM).is_ok() {
+            let deadline = Instant::now() + grace;
+            while !self.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = grace;
+
+        if !self.is_finished() {
+            self.kill();
+        }
+    }
+
+    /// Deliver `signal` to the child's process group, like a terminal does
+    /// for Ctrl+C. The child lock is held across the liveness check so the
+    /// group can't disappear and its id be reused in between.
+    #[cfg(unix)]
+    fn signal(&self, signal: nix::sys::signal::Signal) -> Result<(), AgentError> {
+        let mut child = lock_recover(&self.child, "child");
+        if !matches!(child.try_wait(), Ok(None)) {
+            return Err(AgentError::SignalFailed("proces již skončil".to_string()));
+        }
+        let group = nix::unistd::Pid::from_raw(child.id() as i32);
+        nix::sys::signal::killpg(group, signal).map_err(|e| AgentError::SignalFailed(e.to_string()))
+    }
+
+    /// Raise a console control event in the child's process group, the
+    /// Windows counterpart of `signal`.
+    #[cfg(target_os = "windows")]
+    fn console_event(&self, event: u32) -> Result<(), AgentError> {
+        let mut child = lock_recover(&self.child, "child");
+        if !matches!(child.try_wait(), Ok(None)) {
+            return Err(AgentError::SignalFailed("proces již skončil".to_string()));
+        }
+        // SAFETY: plain call without pointers; the group id is the child's pid
+        let sent = unsafe { windows_sys::Win32::System::Console::GenerateConsoleCtrlEvent(event, child.id()) } != 0;
+        if sent {
+            Ok(())
+        } else {
+            Err(AgentError::SignalFailed(std::io::Error::last_os_error().to_string()))
+        }
+    }
+}
+
+/// Watch a run for `timeout` without output and apply `action`. A notify-only
+/// watcher re-arms once output resumes.
+fn watch_idle(app: AppHandle, id: String, control: Arc<RunControl>, timeout: Duration, action: IdleAction) {
+    std::thread::spawn(move || {
+        let mut notified = false;
+        while !control.is_finished() {
+            let idle = control.idle_for();
+            if idle < timeout {
+                notified = false;
+                std::thread::sleep((timeout - idle).min(Duration::from_millis(250)));
+                continue;
+            }
+            if !notified {
+                // Only claim the agent stopped once its whole tree is gone
+                let stopping = action == IdleAction::Stop;
+                if stopping {
+                    control.mark_stopped(StopReason::IdleTimeout);
+                }
+                let stopped = stopping && control.kill();
+                let _ = app.emit("agent-idle", &AgentIdleEvent {
+                    id: id.clone(),
+                    idle_ms: idle.as_millis() as u64,
+                    stopped,
+                });
+                if stopping {
+                    return;
+                }
+                notified = true;
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+    });
+}
+
+/// Stop a run once it has been going for `limit`, whatever it's doing.
+fn watch_runtime(app: AppHandle, id: String, control: Arc<RunControl>, limit: Duration) {
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        while !control.is_finished() {
+            let elapsed = started.elapsed();
+            if elapsed >= limit {
+                let _ = app.emit("agent-timeout", &AgentTimeoutEvent {
+                    id: id.clone(),
+                    runtime_ms: elapsed.as_millis() as u64,
+                });
+                control.mark_stopped(StopReason::MaxRuntime);
+                control.terminate(STOP_GRACE);
+                return;
+            }
+            std::thread::sleep((limit - elapsed).min(Duration::from_millis(250)));
+        }
+    });
+}
+
+const DEFAULT_BATCH_LINES: usize = 200;
+
+/// Fixed one-second window line budget for `max_lines_per_sec`.
+struct RateLimiter {
+    limit: Option<u32>,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    fn new(limit: Option<u32>) -> Self {
+        Self {
+            limit,
+            window_start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    fn allow(&mut self) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.count = 0;
+        }
+        self.count = self.count.saturating_add(1);
+        self.count <= limit
+    }
+}
+
+/// One decoded piece of agent output.
+struct OutputChunk {
+    text: String,
+    /// The segment ended in a bare `\r` (progress bar, spinner), so the next
+    /// chunk overwrites it rather than starting a new line.
+    partial: bool,
+    /// The line was longer than the cap and was cut off.
+    truncated: bool,
+}
+
+impl OutputChunk {
+    fn line(bytes: &[u8], max_line: usize, encoding: &'static Encoding) -> Self {
+        let truncated = bytes.len() > max_line;
+        OutputChunk {
+            text: encoding.decode_without_bom_handling(&bytes[..bytes.len().min(max_line)]).0.into_owned(),
+            partial: false,
+            truncated,
+        }
+    }
+}
+
+/// Longest line kept by default; the rest of the line is dropped.
+const DEFAULT_MAX_LINE_BYTES: usize = 1024 * 1024;
+
+/// Split a byte stream on `\n` and bare `\r`, decoding each piece lossily
+/// from `encoding` so invalid bytes show up as U+FFFD instead of ending the
+/// stream.
+///
+/// A `\r` is reported as a partial chunk straight away so progress updates
+/// appear live. If it turns out to be half of a `\r\n` that was split
+/// across reads, the same text is re-sent as a complete line.
+///
+/// Lines over `max_line` bytes are emitted cut off as soon as the cap is
+/// reached, and the rest is skipped up to the next separator, so a runaway
+/// line can't grow memory without bound.
+fn output_chunks<R: IoRead>(
+    mut reader: BufReader<R>,
+    max_line: usize,
+    encoding: &'static Encoding,
+) -> impl Iterator<Item = OutputChunk> {
+    let mut pending_cr: Option<String> = None;
+    let mut discarding = false;
+    std::iter::from_fn(move || {
+        let mut buf = Vec::new();
+        loop {
+            let available = match reader.fill_buf() {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    tracing::warn!(error = %e, "reading agent output failed");
+                    return None;
+                }
+            };
+            if available.is_empty() {
+                return (!buf.is_empty()).then(|| OutputChunk::line(&buf, max_line, encoding));
+            }
+
+            let Some(pos) = available.iter().position(|&b| b == b'\n' || b == b'\r') else {
+                let len = available.len();
+                if !discarding {
+                    buf.extend_from_slice(available);
+                }
+                reader.consume(len);
+                pending_cr = None;
+                if buf.len() > max_line {
+                    discarding = true;
+                    return Some(OutputChunk::line(&buf, max_line, encoding));
+                }
+                continue;
+            };
+            let separator = available[pos];
+            if discarding {
+                // End of an over-long line: resynchronize here
+                reader.consume(pos + 1);
+                if separator == b'\r' && reader.buffer().first() == Some(&b'\n') {
+                    reader.consume(1);
+                }
+                discarding = false;
+                continue;
+            }
+            buf.extend_from_slice(&available[..pos]);
+            reader.consume(pos + 1);
+
+            if separator == b'\n' {
+                if let (true, Some(text)) = (buf.is_empty(), pending_cr.take()) {
+                    return Some(OutputChunk { text, partial: false, truncated: false });
+                }
+                pending_cr = None;
+                return Some(OutputChunk::line(&buf, max_line, encoding));
+            }
+
+            let mut chunk = OutputChunk::line(&buf, max_line, encoding);
+            if reader.buffer().first() == Some(&b'\n') {
+                reader.consume(1);
+                pending_cr = None;
+                return Some(chunk);
+            }
+            chunk.partial = true;
+            pending_cr = Some(chunk.text.clone());
+            return Some(chunk);
+        }
+    })
+}
+
+/// `output_chunks`, with escape sequences removed when `strip_ansi` is set.
+fn read_output<R: IoRead>(
+    reader: BufReader<R>,
+    strip_ansi: bool,
+    max_line: usize,
+    encoding: &'static Encoding,
+) -> impl Iterator<Item = OutputChunk> {
+    output_chunks(reader, max_line, encoding).map(move |mut chunk| {
+        if strip_ansi {
+            chunk.text = strip_ansi_escapes::strip_str(&chunk.text);
+        }
+        chunk
+    })
+}
+
+/// Emit `agent-json` if `line` is a JSON object or array. Bare scalars are
+/// left alone so a line like `42` stays plain output.
+fn emit_json(app: &AppHandle, id: &str, line: &str) {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(line) else {
+        return;
+    };
+    if value.is_object() || value.is_array() {
+        let _ = app.emit("agent-json", &AgentJsonEvent {
+            id: id.to_string(),
+            value,
+        });
+    }
+}
+
+/// Send an output event to every channel subscribed to its agent, dropping
+/// channels whose webview has gone away.
+fn forward_to_subscribers(app: &AppHandle, event: &AgentOutputEvent) {
+    let state = app.state::<AgentState>();
+    let mut subscribers = state.lock_subscribers();
+    if let Some(channels) = subscribers.get_mut(&event.id) {
+        channels.retain(|channel| channel.send(event.clone()).is_ok());
+    }
+}
+
+/// Emit an `agent-output` event and forward it to subscribed channels.
+/// Returns whether the emit itself succeeded.
+fn emit_output(app: &AppHandle, event: AgentOutputEvent) -> bool {
+    tracing::trace!(id = %event.id, stream = %event.stream, data = %event.data, "output");
+    forward_to_subscribers(app, &event);
+    app.emit("agent-output", &event).is_ok()
+}
+
+/// Emit stdout lines as they arrive, or coalesced when batching is enabled.
+fn stream_stdout<R: IoRead + Send + 'static>(
+    app: &AppHandle,
+    id: &str,
+    reader: BufReader<R>,
+    options: &RunOptions,
+    control: &Arc<RunControl>,
+) {
+    let mut limiter = RateLimiter::new(options.max_lines_per_sec);
+    let Some(window) = options.batch_ms.map(Duration::from_millis) else {
+        for chunk in read_output(reader, options.strip_ansi, options.max_line(), options.encoding()) {
+            control.record(&chunk);
+            if !limiter.allow() {
+                control.note_dropped();
+                continue;
+            }
+            if options.parse_json_lines && !chunk.partial {
+                emit_json(app, id, &chunk.text);
+            }
+            let delivered = emit_output(app, AgentOutputEvent {
+                id: id.to_string(),
+                data: chunk.text,
+                stream: "stdout".to_string(),
+                partial: chunk.partial,
+                truncated: chunk.truncated,
+            });
+            if !control.note_emit(app, delivered) {
+                break;
+            }
+        }
+        return;
+    };
+    let max_lines = options.batch_lines.unwrap_or(DEFAULT_BATCH_LINES).max(1);
+
+    // Lines are read on a helper thread so the window can expire while the
+    // agent is silent. Partial `\r` segments are only useful live, so batches
+    // carry completed lines only.
+    let (tx, rx) = std::sync::mpsc::channel::<String>();
+    let reader_control = control.clone();
+    let (strip_ansi, max_line, encoding) = (options.strip_ansi, options.max_line(), options.encoding());
+    let span = tracing::Span::current();
+    std::thread::spawn(move || {
+        let _span = span.entered();
+        let control = reader_control;
+        for chunk in read_output(reader, strip_ansi, max_line, encoding).inspect(|chunk| control.record(chunk)).filter(|c| !c.partial) {
+            if !limiter.allow() {
+                control.note_dropped();
+                continue;
+            }
+            if tx.send(chunk.text).is_err() {
+                break;
+            }
+        }
+    });
+
+    let flush = |lines: &mut Vec<String>| {
+        if !lines.is_empty() {
+            // Subscribed channels always get the per-line stream
+            for line in lines.iter() {
+                if options.parse_json_lines {
+                    emit_json(app, id, line);
+                }
+                forward_to_subscribers(app, &AgentOutputEvent {
+                    id: id.to_string(),
+                    data: line.clone(),
+                    stream: "stdout".to_string(),
+                    partial: false,
+                    truncated: false,
+                });
+            }
+            let delivered = app
+                .emit("agent-output-batch", &AgentOutputBatch {
+                    id: id.to_string(),
+                    stream: "stdout".to_string(),
+                    lines: std::mem::take(lines),
+                })
+                .is_ok();
+            // A kill closes the pipe, which ends the helper thread and
+            // disconnects the channel below
+            control.note_emit(app, delivered);
+        }
+    };
+
+    let mut lines = Vec::new();
+    let mut deadline = Instant::now();
+    loop {
+        let next = if lines.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        };
+        match next {
+            Ok(text) => {
+                if lines.is_empty() {
+                    deadline = Instant::now() + window;
+                }
+                lines.push(text);
+                if lines.len() >= max_lines {
+                    flush(&mut lines);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => flush(&mut lines),
+            Err(RecvTimeoutError::Disconnected) => {
+                flush(&mut lines);
+                break;
+            }
+        }
+    }
+}
+
+/// The program `cmd` launches. On Windows agents are wrapped as
+/// `cmd /c <program> ...` and with `login_shell` as `$SHELL -lc 'exec ...'`,
+/// so the wrapped program is returned instead.
+fn launched_program(cmd: &Command) -> Option<String> {
+    #[cfg(not(target_os = "windows"))]
+    if let Some(line) = login_shell_line(cmd) {
+        return login_shell_program(&line);
+    }
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    #[cfg(target_os = "windows")]
+    let program = {
+        let mut args = cmd.get_args();
+        if program.eq_ignore_ascii_case("cmd") && args.next().is_some_and(|a| a == "/c") {
+            args.next()?.to_string_lossy().into_owned()
+        } else {
+            program
+        }
+    };
+    Some(program)
+}
+
+/// The `exec ...` line of a `login_shell` run, built by `agent_command`.
+#[cfg(not(target_os = "windows"))]
+fn login_shell_line(cmd: &Command) -> Option<String> {
+    let mut args = cmd.get_args();
+    let (Some(flag), Some(line), None) = (args.next(), args.next(), args.next()) else {
+        return None;
+    };
+    let line = line.to_string_lossy();
+    (flag == "-lc" && line.starts_with("exec '")).then(|| line.into_owned())
+}
+
+/// The first word of a login shell line, undoing `shell_quote`.
+#[cfg(not(target_os = "windows"))]
+fn login_shell_program(line: &str) -> Option<String> {
+    let mut rest = line.strip_prefix("exec '")?;
+    let mut program = String::new();
+    loop {
+        let end = rest.find('\'')?;
+        program.push_str(&rest[..end]);
+        match rest[end..].strip_prefix("'\\''") {
+            Some(after) => {
+                program.push('\'');
+                rest = after;
+            }
+            None => return Some(program),
+        }
+    }
+}
+
+/// How long looking an agent up through the user's login shell may take.
+#[cfg(not(target_os = "windows"))]
+const LOGIN_LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Look up the program `cmd` launches on the PATH it will run with, which
+/// `path_prepend` or, for a `login_shell` run, the login profile may have
+/// changed from ours.
+fn resolved_program(cmd: &Command) -> Option<String> {
+    let program = launched_program(cmd)?;
+    let path = std::path::Path::new(&program);
+    if path.is_absolute() {
+        return path.is_file().then_some(program);
+    }
+    #[cfg(not(target_os = "windows"))]
+    if login_shell_line(cmd).is_some() {
+        let mut lookup = Command::new(cmd.get_program());
+        lookup.args(["-lc", &format!("command -v {}", shell_quote(&program))]);
+        // Keep the environment the agent gets, e.g. `path_prepend`
+        for (key, value) in cmd.get_envs() {
+            match value {
+                Some(value) => lookup.env(key, value),
+                None => lookup.env_remove(key),
+            };
+        }
+        return probe_first_line(lookup, LOGIN_LOOKUP_TIMEOUT)
+            .filter(|found| std::path::Path::new(found).is_absolute());
+    }
+    match cmd.get_envs().find(|(key, _)| *key == "PATH") {
+        Some((_, Some(child_path))) => scan_path_in(&program, child_path),
+        _ => find_on_path(&program),
+    }
+}
+
+/// Id of the known agent `cmd` launches, matched on the file stem so
+/// `/usr/local/bin/claude` and `claude.cmd` both count as `claude`.
+fn signature_id(cmd: &Command) -> Option<String> {
+    let program = launched_program(cmd)?;
+    let stem = std::path::Path::new(&program).file_stem()?.to_string_lossy().into_owned();
+    agent_signatures()
+        .into_iter()
+        .find(|sig| sig.answers_to(&stem))
+        .map(|sig| sig.command.to_string())
+}
+
+/// Read `reader` to the end as raw bytes, counting each read as activity.
+fn capture_bytes<R: IoRead>(mut reader: R, control: &RunControl) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                control.touch();
+                bytes.extend_from_slice(&buf[..n]);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                tracing::warn!(error = %e, "reading binary output failed");
+                break;
+            }
+        }
+    }
+    bytes
+}
+
+/// Whether the child ended from Ctrl+C: exit code 130 by shell convention,
+/// or killed by SIGINT on Unix.
+fn is_interrupt(status: std::process::ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if status.signal() == Some(libc::SIGINT) {
+            return true;
+        }
+    }
+    status.code() == Some(130)
+}
+
+/// Whether a finished run counts as a success. A stop reason decides first,
+/// because an agent that handles SIGTERM may exit 0 after a timeout; only
+/// then is the exit code checked.
+fn run_succeeded(code: Option<i32>, interrupted: bool, stop_reason: Option<StopReason>, success_codes: &[i32]) -> bool {
+    match stop_reason {
+        Some(StopReason::Manual) => true,
+        Some(_) => false,
+        None => interrupted
+            || match code {
+                None => false,
+                Some(code) if success_codes.is_empty() => code == 0,
+                Some(code) => success_codes.contains(&code),
+            },
+    }
+}
+
+/// Killed by a signal, which for a run nobody stopped means it crashed.
+fn is_crash(status: std::process::ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal().is_some_and(|signal| signal != libc::SIGINT)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        false
+    }
+}
+
+/// Run `cmd` to completion, streaming its output and the final `agent-done`
+/// event under `id`. Returns the exit code, `Some(-1)` if it never started.
+fn run_to_completion(app: &AppHandle, id: &str, mut cmd: Command, mut run: PreparedRun) -> Option<i32> {
+    // Entered for the whole run; the reader threads open child spans so
+    // their events carry the run id too
+    let _span = tracing::info_span!("run", id).entered();
+    let options = &run.options;
+    if run.stdin_file.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    if !options.path_prepend.is_empty() {
+        // `cmd /c` passes its environment on, so Windows gets the same PATH
+        let inherited = std::env::var_os("PATH").unwrap_or_default();
+        let dirs = options
+            .path_prepend
+            .iter()
+            .map(std::path::PathBuf::from)
+            .chain(std::env::split_paths(&inherited));
+        if let Ok(path) = std::env::join_paths(dirs) {
+            cmd.env("PATH", path);
+        }
+    }
+    // Own process group, so console control events from `send_signal`
+    // reach the agent and not AgentHub
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_SUSPENDED: u32 = 0x0000_0004;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        // Suspended until `RunControl::new` has put it in its job object, so
+        // nothing it starts can escape the job
+        cmd.creation_flags(options.priority.creation_flags() | CREATE_NEW_PROCESS_GROUP | CREATE_SUSPENDED);
+    }
+    // Own group, so stopping the run reaches everything the agent starts
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+        if options.priority != Priority::Normal {
+            // Renice in the child before exec, so anything the agent forks
+            // inherits it. Best effort: a failed renice (raising without
+            // privileges) leaves the default priority.
+            let niceness = options.priority.niceness();
+            // SAFETY: setpriority is async-signal-safe and touches no
+            // memory, so it may run between fork and exec
+            unsafe {
+                cmd.pre_exec(move || {
+                    libc::setpriority(libc::PRIO_PROCESS, 0, niceness);
+                    Ok(())
+                });
+            }
+        }
+    }
+    let pipes = if options.merge_stderr {
+        os_pipe::pipe().and_then(|(reader, writer)| {
+            cmd.stderr(writer.try_clone()?).stdout(writer);
+            Ok(Some(reader))
+        })
+    } else {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        Ok(None)
+    };
+    let child = pipes.and_then(|merged| Ok((cmd.spawn()?, merged)));
+
+    let (code, interrupted, stop_reason) = match child {
+        Ok((mut child, merged)) => {
+            if merged.is_some() {
+                // Drop our copies of the write end, or the reader never
+                // sees EOF
+                cmd.stdout(Stdio::null()).stderr(Stdio::null());
+            }
+
+            tracing::info!(id, pid = child.id(), program = ?cmd.get_program(), "agent spawned");
+            let _ = app.emit("agent-spawned", &AgentSpawnedEvent {
+                id: id.to_string(),
+                pid: child.id(),
+                command: cmd.get_program().to_string_lossy().into_owned(),
+                args: cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect(),
+                exe_path: resolved_program(&cmd),
+                signature_id: signature_id(&cmd),
+            });
+
+            // Feed stdin on its own thread; io::copy streams it in chunks
+            // and dropping the pipe afterwards closes the child's stdin
+            if let (Some(mut file), Some(mut stdin)) = (run.stdin_file.take(), child.stdin.take()) {
+                let app = app.clone();
+                let id = id.to_string();
+                let span = tracing::info_span!("stdin");
+                std::thread::spawn(move || {
+                    let _span = span.entered();
+                    if let Err(e) = std::io::copy(&mut file, &mut stdin) {
+                        // The child closing stdin early is its own business
+                        if e.kind() != std::io::ErrorKind::BrokenPipe {
+                            tracing::warn!(id = %id, error = %e, "writing stdin_file failed");
+                            emit_output(&app, AgentOutputEvent {
+                                id,
+                                data: AgentError::IoError(e.to_string()).to_string(),
+                                stream: "stderr".to_string(),
+                                partial: false,
+                                truncated: false,
+                            });
+                        }
+                    }
+                });
+            }
+
+            let stdout = child.stdout.take();
+            let stderr = child.stderr.take();
+            let control = Arc::new(RunControl::new(child));
+            app.state::<AgentState>().lock_running().insert(id.to_string(), control.clone());
+
+            if let Some(timeout) = options.idle_timeout_ms.map(Duration::from_millis) {
+                watch_idle(app.clone(), id.to_string(), control.clone(), timeout, options.idle_action);
+            }
+            if let Some(limit) = options.max_runtime_ms.map(Duration::from_millis) {
+                watch_runtime(app.clone(), id.to_string(), control.clone(), limit);
+            }
+
+            // Collect stderr on its own thread so it counts as activity and
+            // a full stderr pipe can't stall the child
+            let stderr_reader = stderr.map(|stderr| {
+                let app = app.clone();
+                let id = id.to_string();
+                let control = control.clone();
+                let patterns = run.error_patterns.clone();
+                let (strip_ansi, max_line, encoding) = (options.strip_ansi, options.max_line(), options.encoding());
+                let flood_bytes = options.stderr_flood_bytes.unwrap_or(DEFAULT_STDERR_FLOOD_BYTES);
+                let span = tracing::info_span!("stderr");
+                std::thread::spawn(move || {
+                    let _span = span.entered();
+                    let _reading = ReaderFlag::raise(&control.stderr_reading);
+                    let mut tail = StderrTail::new(STDERR_TAIL_BYTES);
+                    let mut bytes = 0u64;
+                    let mut flooded = false;
+                    for chunk in read_output(BufReader::new(stderr), strip_ansi, max_line, encoding) {
+                        control.record(&chunk);
+                        bytes += chunk.text.len() as u64;
+                        if !flooded && bytes >= flood_bytes {
+                            // Notify once per run; the run itself carries on
+                            flooded = true;
+                            let _ = app.emit("agent-stderr-flood", &AgentStderrFloodEvent {
+                                id: id.clone(),
+                                bytes,
+                            });
+                        }
+                        if chunk.partial {
+                            continue;
+                        }
+                        if let Some(pattern) = patterns.iter().find(|p| p.is_match(&chunk.text)) {
+                            let _ = app.emit("agent-error-detected", &AgentErrorDetectedEvent {
+                                id: id.clone(),
+                                line: chunk.text.clone(),
+                                pattern: pattern.as_str().to_string(),
+                            });
+                        }
+                        tail.push(chunk.text);
+                    }
+                    tail.finish()
+                })
+            });
+
+            // Read stdout line by line and stream to frontend
+            let stdout_span = tracing::info_span!("stdout").entered();
+            let reading = ReaderFlag::raise(&control.stdout_reading);
+            if options.capture_binary {
+                let bytes = match (merged, stdout) {
+                    (Some(merged), _) => capture_bytes(merged, &control),
+                    (None, Some(stdout)) => capture_bytes(stdout, &control),
+                    (None, None) => Vec::new(),
+                };
+                app.state::<AgentState>().lock_binary().insert(id.to_string(), bytes);
+            } else if let Some(merged) = merged {
+                stream_stdout(app, id, BufReader::new(merged), options, &control);
+            } else if let Some(stdout) = stdout {
+                stream_stdout(app, id, BufReader::new(stdout), options, &control);
+            }
+            drop(reading);
+            drop(stdout_span);
+
+            if let Some((err_text, truncated)) = stderr_reader.and_then(|reader| reader.join().ok()) {
+                if !err_text.trim().is_empty() {
+                    emit_output(app, AgentOutputEvent {
+                        id: id.to_string(),
+                        data: err_text.trim().to_string(),
+                        stream: "stderr".to_string(),
+                        partial: false,
+                        truncated,
+                    });
+                }
+            }
+
+            // Wait for exit
+            let status = control.wait();
+            control.finished.store(true, Ordering::Relaxed);
+            app.state::<AgentState>().lock_running().remove(id);
+            let stop_reason = lock_recover(&control.stop_reason, "stop reason")
+                .or_else(|| status.is_some_and(is_crash).then_some(StopReason::Crashed));
+            (status.and_then(|s| s.code()), status.is_some_and(is_interrupt), stop_reason)
+        }
+        Err(e) => {
+            tracing::warn!(id, error = %e, "spawn failed");
+            emit_output(app, AgentOutputEvent {
+                id: id.to_string(),
+                data: AgentError::SpawnFailed(e.to_string()).to_string(),
+                stream: "stderr".to_string(),
+                partial: false,
+                truncated: false,
+            });
+            (Some(-1), false, None)
+        }
+    };
+
+    tracing::info!(id, ?code, "agent finished");
+    let success = run_succeeded(code, interrupted, stop_reason, &options.success_codes);
+    let _ = app.emit("agent-done", &AgentDoneEvent {
+        id: id.to_string(),
+        code,
+        success,
+        interrupted,
+        stop_reason,
+    });
+    code
+}
+
+/// Reject ids that are empty or couldn't safely be used as a filename:
+/// only ASCII letters, digits, `-`, `_` and `.` are allowed, and a leading
+/// `.` is refused so `.` and `..` can't slip through.
+fn validate_id(id: &str) -> Result<(), AgentError> {
+    let safe = id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if id.is_empty() || !safe || id.starts_with('.') {
+        return Err(AgentError::InvalidInput(format!("id agenta '{}'", id)));
+    }
+    Ok(())
+}
+
+/// Mark `id` busy and run `cmd` on a background thread, streaming its output
+/// back via events until it exits.
+fn start_run(
+    id: String,
+    cmd: Command,
+    mut options: RunOptions,
+    state: &AgentState,
+    app: AppHandle,
+) -> Result<(), AgentError> {
+    validate_id(&id)?;
+    let on_exit = options.on_exit.take();
+    if let Some(hook) = &on_exit {
+        // A hook relaunching its own agent would never stop
+        if hook.id == id {
+            return Err(AgentError::InvalidInput(format!("on_exit spouští stejného agenta '{}'", id)));
+        }
+        hook.validate()?;
+    }
+    let run = options.prepare()?;
+    state.claim(&id)?;
+
+    std::thread::spawn(move || {
+        let code = run_to_completion(&app, &id, cmd, run);
+
+        // Mark as no longer busy
+        app.state::<AgentState>().release(&id);
+
+        if let Some(hook) = on_exit {
+            run_exit_hook(&app, &id, code, *hook);
+        }
+    });
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentHookRanEvent {
+    /// The agent that exited.
+    pub id: String,
+    pub hook_id: String,
+    /// Why the hook couldn't be started, if it couldn't.
+    pub error: Option<String>,
+}
+
+/// Start the `on_exit` run of `id` as a regular managed run.
+fn run_exit_hook(app: &AppHandle, id: &str, code: Option<i32>, hook: RunSpec) {
+    let hook_id = hook.id.clone();
+    let started = hook.command().and_then(|mut cmd| {
+        cmd.env("AGENT_HUB_EXITED_ID", id)
+            .env("AGENT_HUB_EXIT_CODE", code.map(|c| c.to_string()).unwrap_or_default());
+        let options = hook.options.unwrap_or_default();
+        start_run(hook.id, cmd, options, &app.state::<AgentState>(), app.clone())
+    });
+    let _ = app.emit("agent-hook-ran", &AgentHookRanEvent {
+        id: id.to_string(),
+        hook_id,
+        error: started.err().map(|e| e.to_string()),
+    });
+}
+
+/// Run a one-shot message through a CLI agent.
+/// Spawns `<command> -p "<message>"`, streams output back via events.
+#[tauri::command]
+fn run_agent(
+    id: String,
+    command: String,
+    message: String,
+    options: Option<RunOptions>,
+    state: State<'_, AgentState>,
+    app: AppHandle,
+) -> Result<(), AgentError> {
+    let options = options.unwrap_or_default();
+    let cmd = agent_command(&command, &message, options.login_shell)?;
+    start_run(id, cmd, options, &state, app)
+}
+
+/// One entry of a `run_agent_batch` call or an `on_exit` hook; same fields
+/// as `run_agent`, or a `script` for the shell like `run_agent_shell`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunSpec {
+    pub id: String,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub message: String,
+    /// Run this through the shell instead of `<command> -p <message>`, e.g.
+    /// `notify-send "Agent done"` as a hook.
+    #[serde(default)]
+    pub script: Option<String>,
+    #[serde(default)]
+    pub options: Option<RunOptions>,
+}
+
+impl RunSpec {
+    fn command(&self) -> Result<Command, AgentError> {
+        let login_shell = self.options.as_ref().is_some_and(|options| options.login_shell);
+        match &self.script {
+            Some(script) => shell_command(script),
+            None => agent_command(&self.command, &self.message, login_shell),
+        }
+    }
+
+    /// Check what starting this spec would reject, so a bad `on_exit` hook
+    /// fails the run that carries it instead of surfacing after it exits.
+    fn validate(&self) -> Result<(), AgentError> {
+        validate_id(&self.id)?;
+        self.command()?;
+        if let Some(options) = &self.options {
+            if let Some(hook) = &options.on_exit {
+                hook.validate()?;
+            }
+            options.clone().prepare()?;
+        }
+        Ok(())
+    }
+}
+
+/// Start several one-shot runs in order. Each entry gets its own result, so
+/// one bad spec doesn't stop the rest from launching. Results serialize as
+/// `{ "Ok": null }` or `{ "Err": { kind, message } }`.
+#[tauri::command]
+fn run_agent_batch(
+    runs: Vec<RunSpec>,
+    state: State<'_, AgentState>,
+    app: AppHandle,
+) -> Vec<Result<(), AgentError>> {
+    runs.into_iter()
+        .map(|spec| {
+            let cmd = spec.command()?;
+            start_run(spec.id, cmd, spec.options.unwrap_or_default(), &state, app.clone())
+        })
+        .collect()
+}
+
+/// Build the process `run_agent` launches for `command` and `message`,
+/// optionally through the user's login shell (see `RunOptions::login_shell`).
+fn agent_command(command: &str, message: &str, login_shell: bool) -> Result<Command, AgentError> {
+    // An empty program name only yields a confusing OS error
+    let command = command.trim();
+    if command.is_empty() {
+        return Err(AgentError::InvalidInput("prázdný příkaz".to_string()));
+    }
+
+    // Build command: cmd /c <command> -p "<message>". Absolute paths (the
+    // exact binary the user picked) and paths with spaces are spawned
+    // directly: cmd would strip the quotes around a spaced path, while a
+    // direct spawn handles quoting, Unicode and .cmd shims itself.
+    #[cfg(target_os = "windows")]
+    let cmd = if std::path::Path::new(command).is_absolute() || command.contains(char::is_whitespace) {
+        let mut cmd = Command::new(command);
+        cmd.args(["-p", message]);
+        cmd
+    } else {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/c", command, "-p", message]);
+        cmd
+    };
+    #[cfg(target_os = "windows")]
+    let _ = login_shell;
+
+    // `exec` replaces the shell, so the pid, signals and exit code are the
+    // agent's own.
+    #[cfg(not(target_os = "windows"))]
+    let cmd = if login_shell {
+        let shell = std::env::var_os("SHELL").filter(|s| !s.is_empty()).unwrap_or_else(|| "/bin/sh".into());
+        let line = format!("exec {}", [command, "-p", message].map(shell_quote).join(" "));
+        let mut cmd = Command::new(shell);
+        cmd.args(["-lc", &line]);
+        cmd
+    } else {
+        let mut cmd = Command::new(command);
+        cmd.args(["-p", message]);
+        cmd
+    };
+
+    Ok(cmd)
+}
+
+/// Quote `arg` as a single word for a POSIX shell line.
+#[cfg(not(target_os = "windows"))]
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunPreview {
+    pub resolved_path: Option<String>,
+    /// The full command line, with arguments containing spaces quoted.
+    pub would_run: String,
+    pub available: bool,
+}
+
+/// Show what `run_agent` would launch without spawning anything, so typos in
+/// the command surface before a run. Resolving the program searches PATH,
+/// so it runs on a blocking thread.
+#[tauri::command]
+async fn run_agent_dry_run(command: String, message: String) -> Result<RunPreview, AgentError> {
+    tauri::async_runtime::spawn_blocking(move || preview_run(&command, &message))
+        .await
+        .map_err(|e| AgentError::SpawnFailed(e.to_string()))?
+}
+
+fn preview_run(command: &str, message: &str) -> Result<RunPreview, AgentError> {
+    let cmd = agent_command(command, message, false)?;
+    let would_run = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|part| {
+            let part = part.to_string_lossy();
+            if part.is_empty() || part.contains(char::is_whitespace) {
+                format!("\"{}\"", part)
+            } else {
+                part.into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let resolved_path = resolved_program(&cmd);
+    Ok(RunPreview {
+        available: resolved_path.is_some(),
+        resolved_path,
+        would_run,
+    })
+}
+
+/// Run a script through the user's shell (`sh -c` / `cmd /c`), so pipes and
+/// variable expansion work. Output is streamed the same way as `run_agent`;
+/// prefer `run_agent` when no shell features are needed.
+#[tauri::command]
+fn run_agent_shell(
+    id: String,
+    script: String,
+    options: Option<RunOptions>,
+    state: State<'_, AgentState>,
+    app: AppHandle,
+) -> Result<(), AgentError> {
+    let cmd = shell_command(&script)?;
+    start_run(id, cmd, options.unwrap_or_default(), &state, app)
+}
+
+/// Build the `sh -c` / `cmd /c` process for `script`.
+fn shell_command(script: &str) -> Result<Command, AgentError> {
+    if script.trim().is_empty() {
+        return Err(AgentError::InvalidInput("prázdný skript".to_string()));
+    }
+
+    #[cfg(target_os = "windows")]
+    let cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/c", script]);
+        cmd
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    let cmd = {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    };
+
+    Ok(cmd)
+}
+
+/// One scripted output line for `spawn_mock_agent`.
+#[cfg(any(debug_assertions, feature = "mock"))]
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockLine {
+    pub text: String,
+    #[serde(default = "MockLine::default_stream")]
+    pub stream: String,
+    /// Pause before emitting this line.
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+#[cfg(any(debug_assertions, feature = "mock"))]
+impl MockLine {
+    fn default_stream() -> String {
+        "stdout".to_string()
+    }
+}
+
+/// Replay a scripted run as `agent-output` events followed by `agent-done`
+/// with `exit_code`, so the UI can be exercised without real CLIs installed.
+/// Only built in debug builds or with the `mock` feature.
+#[cfg(any(debug_assertions, feature = "mock"))]
+#[tauri::command]
+fn spawn_mock_agent(
+    id: String,
+    script: Vec<MockLine>,
+    exit_code: Option<i32>,
+    state: State<'_, AgentState>,
+    app: AppHandle,
+) -> Result<(), AgentError> {
+    validate_id(&id)?;
+    state.claim(&id)?;
+
+    std::thread::spawn(move || {
+        for line in script {
+            std::thread::sleep(Duration::from_millis(line.delay_ms));
+            emit_output(&app, AgentOutputEvent {
+                id: id.clone(),
+                data: line.text,
+                stream: line.stream,
+                partial: false,
+                truncated: false,
+            });
+        }
+        let _ = app.emit("agent-done", &AgentDoneEvent {
+            id: id.clone(),
+            code: Some(exit_code.unwrap_or(0)),
+            success: exit_code.unwrap_or(0) == 0,
+            interrupted: false,
+            stop_reason: None,
+        });
+        app.state::<AgentState>().release(&id);
+    });
+
+    Ok(())
+}
+
+/// Subscribe a channel to one agent's output. Each channel receives that
+/// agent's `AgentOutputEvent`s in order, independent of the global
+/// `agent-output` event. Returns the channel id for `unsubscribe_agent_output`;
+/// channels whose webview is gone are dropped automatically.
+#[tauri::command]
+fn subscribe_agent_output(
+    id: String,
+    channel: Channel<AgentOutputEvent>,
+    state: State<'_, AgentState>,
+) -> u32 {
+    let channel_id = channel.id();
+    state.lock_subscribers().entry(id).or_default().push(channel);
+    channel_id
+}
+
+#[tauri::command]
+fn unsubscribe_agent_output(id: String, channel_id: u32, state: State<'_, AgentState>) {
+    let mut subscribers = state.lock_subscribers();
+    if let Some(channels) = subscribers.get_mut(&id) {
+        channels.retain(|channel| channel.id() != channel_id);
+        if channels.is_empty() {
+            subscribers.remove(&id);
+        }
+    }
+}
+
+/// Check if an agent is currently busy processing.
+#[tauri::command]
+fn is_agent_busy(
+    id: String,
+    state: State<'_, AgentState>,
+) -> bool {
+    state.lock_busy().contains(&id)
+}
+
+/// Take the stdout captured by the agent's last `capture_binary` run, as an
+/// `ArrayBuffer` on the frontend. Available once `agent-done` has fired;
+/// the bytes are handed over once and then freed.
+#[tauri::command]
+fn get_agent_binary(id: String, state: State<'_, AgentState>) -> Result<tauri::ipc::Response, AgentError> {
+    let bytes = state.lock_binary().remove(&id).ok_or(AgentError::NotFound(id))?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+/// Output throughput of an agent's current run, to spot agents flooding the
+/// event bus. Fails with `not_found` once the run has ended.
+#[tauri::command]
+fn get_agent_metrics(id: String, state: State<'_, AgentState>) -> Result<OutputMetrics, AgentError> {
+    let control = state.lock_running().get(&id).cloned().ok_or(AgentError::NotFound(id))?;
+    Ok(control.metrics())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReaderStatus {
+    pub stdout_reader_alive: bool,
+    /// Always false when stderr is merged into stdout.
+    pub stderr_reader_alive: bool,
+}
+
+/// Whether the output readers of an agent's current run are still going,
+/// to confirm output is actually being captured. Fails with `not_found`
+/// once the run has ended.
+#[tauri::command]
+fn get_reader_status(id: String, state: State<'_, AgentState>) -> Result<ReaderStatus, AgentError> {
+    let control = state.lock_running().get(&id).cloned().ok_or(AgentError::NotFound(id))?;
+    Ok(ReaderStatus {
+        stdout_reader_alive: control.stdout_reading.load(Ordering::Relaxed),
+        stderr_reader_alive: control.stderr_reading.load(Ordering::Relaxed),
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotalResources {
+    /// Summed over agents; can exceed 100 on multi-core machines.
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    /// Running agents that could be sampled.
+    pub agents: usize,
+}
+
+/// CPU and memory used by all running agents and the processes they
+/// started. Descendants matter because the direct child is often only a
+/// `cmd /c` or shell wrapper. CPU is measured since the previous call, so
+/// the first call reports 0. Sampling reads `/proc` (or the platform
+/// equivalent), so it runs on a blocking thread.
+#[tauri::command]
+async fn get_total_resources(app: AppHandle) -> Result<TotalResources, AgentError> {
+    tauri::async_runtime::spawn_blocking(move || total_resources(&app.state::<AgentState>()))
+        .await
+        .map_err(|e| AgentError::SpawnFailed(e.to_string()))
+}
+
+/// The process table is only listed with parent links to find the agents'
+/// trees; CPU and memory are then read for those processes alone.
+fn total_resources(state: &AgentState) -> TotalResources {
+    let roots: Vec<sysinfo::Pid> = state
+        .lock_running()
+        .values()
+        .map(|control| sysinfo::Pid::from_u32(control.pid))
+        .collect();
+
+    if roots.is_empty() {
+        return TotalResources {
+            cpu_percent: 0.0,
+            memory_bytes: 0,
+            agents: 0,
+        };
+    }
+    let mut system = state.lock_system();
+    system.refresh_processes_specifics(sysinfo::ProcessesToUpdate::All, true, sysinfo::ProcessRefreshKind::nothing());
+    let mut children: std::collections::HashMap<sysinfo::Pid, Vec<sysinfo::Pid>> = std::collections::HashMap::new();
+    for (pid, process) in system.processes() {
+        if let Some(parent) = process.parent() {
+            children.entry(parent).or_default().push(*pid);
+        }
+    }
+
+    let mut trees = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for root in roots.into_iter().filter(|root| system.process(*root).is_some()) {
+        let mut tree = Vec::new();
+        let mut pending = vec![root];
+        while let Some(pid) = pending.pop() {
+            // A pid reused by an unrelated process could form a cycle
+            if !seen.insert(pid) {
+                continue;
+            }
+            tree.push(pid);
+            pending.extend(children.get(&pid).into_iter().flatten());
+        }
+        trees.push(tree);
+    }
+
+    let tracked: Vec<sysinfo::Pid> = trees.iter().flatten().copied().collect();
+    system.refresh_processes_specifics(
+        sysinfo::ProcessesToUpdate::Some(&tracked),
+        false,
+        sysinfo::ProcessRefreshKind::nothing().with_cpu().with_memory(),
+    );
+    let mut totals = TotalResources {
+        cpu_percent: 0.0,
+        memory_bytes: 0,
+        agents: trees.len(),
+    };
+    for process in tracked.iter().filter_map(|pid| system.process(*pid)) {
+        totals.cpu_percent += process.cpu_usage();
+        totals.memory_bytes += process.memory();
+    }
+    totals
+}
+
+/// What this build of the backend can do on the current platform, so the
+/// frontend only offers controls that will work.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// `std::env::consts::OS`, e.g. `linux`, `macos`, `windows`.
+    pub platform: String,
+    pub pty: bool,
+    pub pause_resume: bool,
+    /// Stopping a run also stops everything it started (a process group on
+    /// Unix, a job object on Windows).
+    pub process_groups: bool,
+    /// Names accepted by `send_signal`; empty where signals are unsupported.
+    pub signals: Vec<String>,
+    /// Whether `RunOptions::priority` has an effect.
+    pub priority: bool,
+    pub mock_agent: bool,
+}
+
+#[tauri::command]
+fn get_capabilities() -> Capabilities {
+    #[cfg(unix)]
+    let signals = nix::sys::signal::Signal::iterator()
+        .map(|signal| signal.as_str().to_string())
+        .collect();
+
+    #[cfg(target_os = "windows")]
+    let signals = vec!["CTRL_C".to_string(), "CTRL_BREAK".to_string()];
+
+    #[cfg(not(any(unix, target_os = "windows")))]
+    let signals = Vec::new();
+
+    Capabilities {
+        platform: std::env::consts::OS.to_string(),
+        pty: false,
+        pause_resume: false,
+        process_groups: cfg!(any(unix, target_os = "windows")),
+        signals,
+        priority: cfg!(any(unix, target_os = "windows")),
+        mock_agent: cfg!(any(debug_assertions, feature = "mock")),
+    }
+}
+
+/// Parse `SIGINT`, `sigint` or `INT` into a signal.
+#[cfg(unix)]
+fn parse_signal(name: &str) -> Result<nix::sys::signal::Signal, AgentError> {
+    let upper = name.trim().to_ascii_uppercase();
+    let full = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+    full.parse()
+        .map_err(|_| AgentError::UnsupportedSignal(name.to_string()))
+}
+
+/// Parse `CTRL_C` / `SIGINT` or `CTRL_BREAK` / `SIGBREAK` into a console
+/// control event.
+#[cfg(target_os = "windows")]
+fn parse_console_event(name: &str) -> Result<u32, AgentError> {
+    use windows_sys::Win32::System::Console::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+    match name.trim().to_ascii_uppercase().trim_start_matches("SIG") {
+        "CTRL_C" | "INT" => Ok(CTRL_C_EVENT),
+        "CTRL_BREAK" | "BREAK" => Ok(CTRL_BREAK_EVENT),
+        _ => Err(AgentError::UnsupportedSignal(name.to_string())),
+    }
+}
+
+/// Send a signal by name to an agent's process group, e.g. `SIGINT` to
+/// interrupt a generation without killing anything else. Only `SIGTERM` and
+/// `SIGKILL` count as stopping the run; an agent may survive the others.
+///
+/// On Windows `CTRL_C` and `CTRL_BREAK` are sent as console control events
+/// to the agent's process group. A new group starts with Ctrl+C ignored, so
+/// `CTRL_C` only reaches agents that re-enable it.
+#[tauri::command]
+fn send_signal(
+    id: String,
+    signal: String,
+    state: State<'_, AgentState>,
+) -> Result<(), AgentError> {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::Signal;
+        let signal = parse_signal(&signal)?;
+        let control = state
+            .lock_running()
+            .get(&id)
+            .cloned()
+            .ok_or(AgentError::NotFound(id))?;
+        control.signal(signal)?;
+        // An agent may catch SIGINT and carry on, and recording it would hide
+        // a later timeout or crash
+        if matches!(signal, Signal::SIGTERM | Signal::SIGKILL) {
+            control.mark_stopped(StopReason::Manual);
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let event = parse_console_event(&signal)?;
+        let control = state
+            .lock_running()
+            .get(&id)
+            .cloned()
+            .ok_or(AgentError::NotFound(id))?;
+        control.console_event(event)
+    }
+
+    #[cfg(not(any(unix, target_os = "windows")))]
+    {
+        let _ = (id, state);
+        Err(AgentError::UnsupportedSignal(signal))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Agent discovery - real system scan
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredAgent {
+    pub id: String,
+    pub name: String,
+    pub short_name: String,
+    /// The name to run: the signature's command, or the alias that was
+    /// actually found on PATH.
+    pub command: String,
+    pub path: String,
+    pub color: String,
+    pub version: String,
+    pub available: bool,
+    pub source: DiscoverySource,
+}
+
+/// Where a discovered agent was installed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiscoverySource {
+    Npm,
+    Brew,
+    Cargo,
+    Pip,
+    /// Found on PATH but not traced back to a package manager.
+    Path,
+}
+
+struct AgentSignature {
+    command: &'static str,
+    /// Other names the same binary may be installed under, tried after
+    /// `command` when looking on PATH.
+    aliases: &'static [&'static str],
+    name: &'static str,
+    short_name: &'static str,
+    color: &'static str,
+    npm_package: &'static str,
+    brew_formula: &'static str,
+    cargo_crate: &'static str,
+    pip_package: &'static str,
+    /// Flag that prints the version; `get_version` falls back to the usual
+    /// alternatives when this one prints nothing.
+    version_flag: &'static str,
+    /// Extra time this agent needs to print its version (slow Node startup);
+    /// 0 uses the scan's default.
+    version_timeout_ms: u64,
+}
+
+impl AgentSignature {
+    /// `command` followed by its aliases.
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.command).chain(self.aliases.iter().copied())
+    }
+
+    fn answers_to(&self, name: &str) -> bool {
+        self.names().any(|n| n.eq_ignore_ascii_case(name))
+    }
+}
+
+fn agent_signatures() -> Vec<AgentSignature> {
+    vec![
+        AgentSignature { command: "claude",   aliases: &["claude-code"],  name: "Claude Code",  short_name: "CC", color: "#00FF64", npm_package: "@anthropic-ai/claude-code", brew_formula: "",           cargo_crate: "", pip_package: "",           version_flag: "--version", version_timeout_ms: 0 },
+        AgentSignature { command: "codex",    aliases: &[],               name: "Codex CLI",    short_name: "CX", color: "#3B82F6", npm_package: "@openai/codex",             brew_formula: "codex",      cargo_crate: "", pip_package: "",           version_flag: "--version", version_timeout_ms: 0 },
+        AgentSignature { command: "gemini",   aliases: &[],               name: "Gemini CLI",   short_name: "GM", color: "#FFB800", npm_package: "",                          brew_formula: "gemini-cli", cargo_crate: "", pip_package: "",           version_flag: "--version", version_timeout_ms: 0 },
+        AgentSignature { command: "aider",    aliases: &[],               name: "Aider",        short_name: "AI", color: "#9333EA", npm_package: "",                          brew_formula: "aider",      cargo_crate: "", pip_package: "aider-chat", version_flag: "--version", version_timeout_ms: 0 },
+        AgentSignature { command: "cody",     aliases: &[],               name: "Cody CLI",     short_name: "CD", color: "#FF5733", npm_package: "",                          brew_formula: "",           cargo_crate: "", pip_package: "",           version_flag: "--version", version_timeout_ms: 0 },
+        AgentSignature { command: "cursor",   aliases: &["cursor-agent"], name: "Cursor Agent", short_name: "CR", color: "#7C3AED", npm_package: "",                          brew_formula: "",           cargo_crate: "", pip_package: "",           version_flag: "--version", version_timeout_ms: 0 },
+        AgentSignature { command: "amp",      aliases: &[],               name: "Amp",          short_name: "AM", color: "#F59E0B", npm_package: "",                          brew_formula: "",           cargo_crate: "", pip_package: "",           version_flag: "--version", version_timeout_ms: 0 },
+    ]
+}
+
+fn find_on_path(cmd: &str) -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let output = Command::new("where")
+        .arg(cmd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output();
+
+    #[cfg(not(target_os = "windows"))]
+    let output = Command::new("which")
+        .arg(cmd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output();
+
+    match output {
+        Ok(o) if o.status.success() => {
+            let path = String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_string();
+            if path.is_empty() { None } else { Some(path) }
+        }
+        Ok(_) => None,
+        // The lookup tool itself is missing, so walk PATH directly
+        Err(_) => scan_path(cmd),
+    }
+}
+
+/// Pure-Rust fallback for `find_on_path` on systems without `which` /
+/// `where`.
+fn scan_path(cmd: &str) -> Option<String> {
+    scan_path_in(cmd, &std::env::var_os("PATH")?)
+}
+
+/// Find `cmd` in the directories of `path`. On Windows each `PATHEXT`
+/// extension is tried in turn.
+fn scan_path_in(cmd: &str, path: &std::ffi::OsStr) -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let extensions: Vec<String> = std::env::var("PATHEXT")
+        .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    #[cfg(not(target_os = "windows"))]
+    let extensions = [String::new()];
+
+    std::env::split_paths(path)
+        .flat_map(|dir| extensions.iter().map(move |ext| dir.join(format!("{}{}", cmd, ext))))
+        .find(|candidate| is_executable(candidate))
+        .map(|found| found.to_string_lossy().into_owned())
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    let Ok(meta) = path.metadata() else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.is_file() && meta.permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        meta.is_file()
+    }
+}
+
+/// Flags tried after a signature's own `version_flag`, in order.
+const VERSION_FLAG_FALLBACKS: &[&str] = &["--version", "-v", "version"];
+
+/// Return the first non-empty version string and the flag that printed it,
+/// trying `version_flag` first and then the common fallbacks. `timeout`
+/// covers all attempts together. Fallbacks are only tried after a probe
+/// exited without output: a CLI that hangs on one flag usually hangs on
+/// all of them.
+fn get_version<'a>(cmd: &str, version_flag: &'a str, timeout: Duration) -> Option<(&'a str, String)> {
+    let deadline = Instant::now() + timeout;
+    let flags = std::iter::once(version_flag)
+        .chain(VERSION_FLAG_FALLBACKS.iter().copied().filter(|f| *f != version_flag));
+    for flag in flags {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match read_version(cmd, flag, remaining) {
+            Some(version) if !version.is_empty() => return Some((flag, version)),
+            Some(_) => continue,
+            None => break,
+        }
+    }
+    None
+}
+
+/// How long probing one agent's version may take over all flags, including
+/// reading output, unless the caller or the signature asks for longer.
+const DEFAULT_VERSION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Read the first line of `pipe` on a helper thread, so a child that never
+/// writes a newline can't block the caller past its deadline.
+fn first_line<R: IoRead + Send + 'static>(pipe: R) -> std::sync::mpsc::Receiver<String> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = String::new();
+        BufReader::new(pipe).read_line(&mut buf).ok();
+        let _ = tx.send(buf.trim().to_string());
+    });
+    rx
+}
+
+fn read_version(cmd: &str, flag: &str, timeout: Duration) -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let probe = {
+        let mut probe = Command::new("cmd");
+        probe.args(["/c", cmd, flag]);
+        probe
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    let probe = {
+        let mut probe = Command::new(cmd);
+        probe.arg(flag);
+        probe
+    };
+
+    probe_first_line(probe, timeout)
+}
+
+/// Run `cmd` and return the first line it prints on stdout, or on stderr if
+/// stdout is silent. `None` if it can't be started or runs past `timeout`.
+fn probe_first_line(mut cmd: Command, timeout: Duration) -> Option<String> {
+    let child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    let Ok(mut child) = child else {
+        return None;
+    };
+    let deadline = Instant::now() + timeout;
+    let stdout = child.stdout.take().map(first_line);
+    let stderr = child.stderr.take().map(first_line);
+
+    match child.wait_timeout(timeout) {
+        Ok(Some(_)) => {}
+        _ => {
+            // Ignored the flag (e.g. started an interactive session); reap it
+            // so it doesn't linger as a zombie
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+    }
+
+    let read = |rx: Option<std::sync::mpsc::Receiver<String>>| {
+        rx.and_then(|rx| rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok())
+            .unwrap_or_default()
+    };
+    let version = read(stdout);
+    if version.is_empty() {
+        Some(read(stderr))
+    } else {
+        Some(version)
+    }
+}
+
+/// A bare `npm` process to add arguments to. npm is a .cmd shim on Windows,
+/// so it has to go through cmd there.
+fn npm_command() -> Command {
+    #[cfg(target_os = "windows")]
+    let cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/c", "npm"]);
+        cmd
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    let cmd = Command::new("npm");
+
+    cmd
+}
+
+/// Package scans return `None` when the package manager itself couldn't be
+/// run, so discovery diagnostics can tell "not installed" from "no npm".
+fn scan_npm_global() -> Option<Vec<(String, String)>> {
+    let output = npm_command()
+        .args(["list", "-g", "--depth=0", "--json"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output();
+
+    let o = output.ok()?;
+    let mut found = Vec::new();
+    let text = String::from_utf8_lossy(&o.stdout);
+    for sig in agent_signatures() {
+        if !sig.npm_package.is_empty() && text.contains(sig.npm_package) {
+            found.push((sig.npm_package.to_string(), sig.command.to_string()));
+        }
+    }
+    Some(found)
+}
+
+/// Installed Homebrew formulae, from `brew list --versions` (`<formula> <version>...`).
+fn scan_brew() -> Option<Vec<(String, String)>> {
+    let output = Command::new("brew")
+        .args(["list", "--versions"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output();
+
+    let o = output.ok()?;
+    let mut found = Vec::new();
+    let text = String::from_utf8_lossy(&o.stdout);
+    let formulae: std::collections::HashSet<&str> = text
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    for sig in agent_signatures() {
+        if !sig.brew_formula.is_empty() && formulae.contains(sig.brew_formula) {
+            found.push((sig.brew_formula.to_string(), sig.command.to_string()));
+        }
+    }
+    Some(found)
+}
+
+/// Crates installed with `cargo install`. `cargo install --list` prints a
+/// `<crate> v<version>:` header per crate followed by indented binary names.
+fn scan_cargo() -> Option<Vec<(String, String)>> {
+    let output = Command::new("cargo")
+        .args(["install", "--list"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output();
+
+    let o = output.ok()?;
+    Some(match_cargo_installs(&String::from_utf8_lossy(&o.stdout)))
+}
+
+/// Match `cargo install --list` output against the signatures, by crate
+/// name or by an installed binary answering to the agent's names, so agents
+/// installed with `cargo install --git` count too. Returns
+/// `(crate, command)` pairs.
+fn match_cargo_installs(list: &str) -> Vec<(String, String)> {
+    let mut installs: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in list.lines() {
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, bins)) = installs.last_mut() {
+                bins.push(line.trim());
+            }
+        } else if let Some(name) = line.split_whitespace().next() {
+            installs.push((name, Vec::new()));
+        }
+    }
+
+    let mut found = Vec::new();
+    for sig in agent_signatures() {
+        let hit = installs.iter().find(|(name, bins)| {
+            (!sig.cargo_crate.is_empty() && *name == sig.cargo_crate) || bins.iter().any(|bin| sig.answers_to(bin))
+        });
+        if let Some((name, _)) = hit {
+            found.push((name.to_string(), sig.command.to_string()));
+        }
+    }
+    found
+}
+
+/// Python packages from `pip list --format=json`, trying `pip` and then
+/// `pip3`.
+fn scan_pip() -> Option<Vec<(String, String)>> {
+    let output = ["pip", "pip3"].into_iter().find_map(|pip| {
+        Command::new(pip)
+            .args(["list", "--format=json"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+    })?;
+    Some(match_pip_packages(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Match `pip list --format=json` output against the signatures. pip
+/// compares names case-insensitively with `-`, `_` and `.` all equal.
+fn match_pip_packages(json: &str) -> Vec<(String, String)> {
+    #[derive(Deserialize)]
+    struct PipPackage {
+        name: String,
+    }
+    let normalize = |name: &str| name.to_ascii_lowercase().replace(['_', '.'], "-");
+    let installed: std::collections::HashSet<String> = serde_json::from_str::<Vec<PipPackage>>(json)
+        .unwrap_or_default()
+        .iter()
+        .map(|package| normalize(&package.name))
+        .collect();
+
+    let mut found = Vec::new();
+    for sig in agent_signatures() {
+        if !sig.pip_package.is_empty() && installed.contains(&normalize(sig.pip_package)) {
+            found.push((sig.pip_package.to_string(), sig.command.to_string()));
+        }
+    }
+    found
+}
+
+/// Directory a package manager links its executables into, used to tell
+/// which install `find_on_path` actually resolved to.
+fn source_bin_dir(source: DiscoverySource) -> Option<std::path::PathBuf> {
+    let prefix = |mut cmd: Command, args: &[&str]| {
+        cmd.args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .filter(|p| !p.is_empty())
+            .map(std::path::PathBuf::from)
+    };
+
+    match source {
+        // npm puts shims directly in the prefix on Windows, in bin/ elsewhere
+        DiscoverySource::Npm if cfg!(target_os = "windows") => prefix(npm_command(), &["prefix", "-g"]),
+        DiscoverySource::Npm => prefix(npm_command(), &["prefix", "-g"]).map(|p| p.join("bin")),
+        DiscoverySource::Brew => prefix(Command::new("brew"), &["--prefix"]).map(|p| p.join("bin")),
+        DiscoverySource::Cargo => std::env::var_os("CARGO_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .map(|home| std::path::Path::new(&home).join(".cargo"))
+            })
+            .map(|p| p.join("bin")),
+        // Scripts land next to the pip of the same environment
+        DiscoverySource::Pip => ["pip", "pip3"]
+            .into_iter()
+            .find_map(find_on_path)
+            .and_then(|pip| std::path::Path::new(&pip).parent().map(std::path::Path::to_path_buf)),
+        DiscoverySource::Path => None,
+    }
+}
+
+/// Whether the PATH lookup tool (`where` / `which`) can be run at all.
+fn path_lookup_available() -> bool {
+    #[cfg(target_os = "windows")]
+    let lookup = "where";
+
+    #[cfg(not(target_os = "windows"))]
+    let lookup = "which";
+
+    Command::new(lookup)
+        .arg(lookup)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryReport {
+    pub agents: Vec<DiscoveredAgent>,
+    pub diagnostics: DiscoveryDiagnostics,
+}
+
+/// What discovery could and couldn't check, for "no agents found" reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryDiagnostics {
+    pub npm_available: bool,
+    pub brew_available: bool,
+    pub cargo_available: bool,
+    pub pip_available: bool,
+    /// `where` on Windows, `which` elsewhere. Without it PATH is scanned
+    /// directly, which doesn't see shell aliases or functions.
+    pub path_lookup_available: bool,
+    pub probes: Vec<SignatureProbe>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureProbe {
+    pub command: String,
+    pub outcome: ProbeOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeOutcome {
+    Found,
+    /// On PATH, but no version flag printed anything before the timeout.
+    NoVersion,
+    /// A package manager has it, but it isn't reachable on PATH.
+    NotOnPathPackaged,
+    NotOnPath,
+}
+
+/// Scan for known agents. Probing each agent's version gets
+/// `version_timeout` or the signature's own allowance, whichever is longer.
+fn scan_agents(version_timeout: Duration) -> DiscoveryReport {
+    let _span = tracing::info_span!("discovery").entered();
+    let signatures = agent_signatures();
+    let mut found: Vec<DiscoveredAgent> = Vec::new();
+    let mut probes = Vec::new();
+
+    // Scan package managers (npm global, Homebrew, cargo, pip)
+    let npm = scan_npm_global();
+    let brew = scan_brew();
+    let cargo = scan_cargo();
+    let pip = scan_pip();
+    let mut diagnostics = DiscoveryDiagnostics {
+        npm_available: npm.is_some(),
+        brew_available: brew.is_some(),
+        cargo_available: cargo.is_some(),
+        pip_available: pip.is_some(),
+        path_lookup_available: path_lookup_available(),
+        probes: Vec::new(),
+    };
+    let packaged: Vec<(DiscoverySource, String)> = npm
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, command)| (DiscoverySource::Npm, command))
+        .chain(
+            brew.unwrap_or_default()
+                .into_iter()
+                .map(|(_, command)| (DiscoverySource::Brew, command)),
+        )
+        .chain(
+            cargo
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(_, command)| (DiscoverySource::Cargo, command)),
+        )
+        .chain(
+            pip.unwrap_or_default()
+                .into_iter()
+                .map(|(_, command)| (DiscoverySource::Pip, command)),
+        )
+        .collect();
+    let mut bin_dirs = std::collections::HashMap::new();
+
+    // Scan PATH + verify version
+    for sig in &signatures {
+        let sources: Vec<DiscoverySource> = packaged
+            .iter()
+            .filter(|(_, command)| command == sig.command)
+            .map(|(source, _)| *source)
+            .collect();
+
+        // The first name that resolves is the one runs will use
+        let resolved = sig.names().find_map(|name| find_on_path(name).map(|path| (name, path)));
+        if let Some((command, path)) = resolved {
+            let timeout = version_timeout.max(Duration::from_millis(sig.version_timeout_ms));
+            let version = get_version(command, sig.version_flag, timeout)
+                .map(|(_, version)| version)
+                .unwrap_or_default();
+
+            // Credit the package manager whose bin dir holds the binary that
+            // will actually run; anything else is a plain PATH install.
+            let resolved_dir = std::path::Path::new(&path).parent();
+            let source = sources
+                .iter()
+                .copied()
+                .find(|source| {
+                    let dir = bin_dirs.entry(*source).or_insert_with(|| source_bin_dir(*source));
+                    dir.is_some() && dir.as_deref() == resolved_dir
+                })
+                .unwrap_or(DiscoverySource::Path);
+
+            probes.push(SignatureProbe {
+                command: sig.command.to_string(),
+                outcome: if version.is_empty() { ProbeOutcome::NoVersion } else { ProbeOutcome::Found },
+            });
+            found.push(DiscoveredAgent {
+                id: sig.command.to_string(),
+                name: sig.name.to_string(),
+                short_name: sig.short_name.to_string(),
+                command: command.to_string(),
+                path,
+                color: sig.color.to_string(),
+                version,
+                available: true,
+                source,
+            });
+        } else if let Some(&source) = sources.first() {
+            // Installed by a package manager but not reachable on PATH,
+            // so it can't be launched yet.
+            probes.push(SignatureProbe {
+                command: sig.command.to_string(),
+                outcome: ProbeOutcome::NotOnPathPackaged,
+            });
+            found.push(DiscoveredAgent {
+                id: sig.command.to_string(),
+                name: sig.name.to_string(),
+                short_name: sig.short_name.to_string(),
+                command: sig.command.to_string(),
+                path: String::new(),
+                color: sig.color.to_string(),
+                version: String::new(),
+                available: false,
+                source,
+            });
+        } else {
+            probes.push(SignatureProbe {
+                command: sig.command.to_string(),
+                outcome: ProbeOutcome::NotOnPath,
+            });
+        }
+    }
+
+    for probe in &probes {
+        tracing::debug!(command = %probe.command, outcome = ?probe.outcome, "probed");
+    }
+    tracing::info!(found = found.len(), "discovery finished");
+    diagnostics.probes = probes;
+    DiscoveryReport {
+        agents: found,
+        diagnostics,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureProbeDetail {
+    pub command: String,
+    /// Whether `command` is one of the built-in agent signatures. Package
+    /// scans only recognise known agents.
+    pub known: bool,
+    pub path: Option<String>,
+    /// First line printed by the first version flag that printed anything.
+    pub version: String,
+    pub version_flag: Option<String>,
+    /// Package managers that list this agent as installed.
+    pub packaged_by: Vec<DiscoverySource>,
+    pub outcome: ProbeOutcome,
+}
+
+fn probe_one(command: String) -> SignatureProbeDetail {
+    let sig = agent_signatures().into_iter().find(|sig| sig.answers_to(&command));
+    let packaged_by = match sig {
+        Some(_) => [
+            (DiscoverySource::Npm, scan_npm_global()),
+            (DiscoverySource::Brew, scan_brew()),
+            (DiscoverySource::Cargo, scan_cargo()),
+            (DiscoverySource::Pip, scan_pip()),
+        ]
+        .into_iter()
+        .filter(|(_, hits)| hits.iter().flatten().any(|(_, hit)| *hit == command))
+        .map(|(source, _)| source)
+        .collect(),
+        None => Vec::new(),
+    };
+
+    let path = find_on_path(&command);
+    let primary = sig.as_ref().map_or("--version", |sig| sig.version_flag);
+    let allowance = sig.as_ref().map_or(0, |sig| sig.version_timeout_ms);
+    let timeout = DEFAULT_VERSION_TIMEOUT.max(Duration::from_millis(allowance));
+    let (version_flag, version) = path
+        .as_ref()
+        .and_then(|_| get_version(&command, primary, timeout))
+        .map_or((None, String::new()), |(flag, version)| (Some(flag.to_string()), version));
+
+    let outcome = match (&path, version.is_empty(), packaged_by.is_empty()) {
+        (Some(_), false, _) => ProbeOutcome::Found,
+        (Some(_), true, _) => ProbeOutcome::NoVersion,
+        (None, _, false) => ProbeOutcome::NotOnPathPackaged,
+        (None, _, true) => ProbeOutcome::NotOnPath,
+    };
+    SignatureProbeDetail {
+        known: sig.is_some(),
+        command,
+        path,
+        version,
+        version_flag,
+        packaged_by,
+        outcome,
+    }
+}
+
+/// Run discovery for a single command, reporting the resolved path, raw
+/// version output and which package managers list it. A targeted version
+/// of `discover_agents_verbose` for "why isn't my tool showing up".
+#[tauri::command]
+async fn probe_signature(command: String) -> Result<SignatureProbeDetail, AgentError> {
+    tauri::async_runtime::spawn_blocking(move || probe_one(command))
+        .await
+        .map_err(|e| AgentError::SpawnFailed(e.to_string()))
+}
+
+fn version_timeout(ms: Option<u64>) -> Duration {
+    ms.map(Duration::from_millis).unwrap_or(DEFAULT_VERSION_TIMEOUT)
+}
+
+/// Scan for known agents. `version_timeout_ms` overrides the default time
+/// probing each agent's version may take, for machines with slow CLI
+/// startup.
+#[tauri::command]
+async fn discover_agents(
+    version_timeout_ms: Option<u64>,
+    state: State<'_, AgentState>,
+) -> Result<Vec<DiscoveredAgent>, AgentError> {
+    Ok(discover(version_timeout_ms, &state).await?.agents)
+}
+
+/// Same scan as `discover_agents`, plus a report of which tools were
+/// available and why each known agent was or wasn't found.
+#[tauri::command]
+async fn discover_agents_verbose(
+    version_timeout_ms: Option<u64>,
+    state: State<'_, AgentState>,
+) -> Result<DiscoveryReport, AgentError> {
+    discover(version_timeout_ms, &state).await
+}
+
+/// Run the scan on a blocking thread, since it starts a dozen or more
+/// processes, and remember the agents it found.
+async fn discover(version_timeout_ms: Option<u64>, state: &AgentState) -> Result<DiscoveryReport, AgentError> {
+    let timeout = version_timeout(version_timeout_ms);
+    let report = tauri::async_runtime::spawn_blocking(move || scan_agents(timeout))
+        .await
+        .map_err(|e| AgentError::SpawnFailed(e.to_string()))?;
+    *state.lock_discovered() = report.agents.clone();
+    Ok(report)
+}
+
+/// A known agent from the built-in signature list, installed or not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureInfo {
+    pub command: String,
+    pub aliases: Vec<String>,
+    pub name: String,
+    pub short_name: String,
+    pub color: String,
+    /// Package to install it from, per package manager.
+    pub npm_package: Option<String>,
+    pub brew_formula: Option<String>,
+    pub cargo_crate: Option<String>,
+    pub pip_package: Option<String>,
+    /// Found on PATH under its command or an alias.
+    pub installed: bool,
+}
+
+/// Every agent AgentHub knows about, for an install catalog. Only a PATH
+/// lookup is done, so this is much quicker than `discover_agents`, but it
+/// still touches the disk and runs on a blocking thread.
+#[tauri::command]
+async fn list_signatures() -> Result<Vec<SignatureInfo>, AgentError> {
+    tauri::async_runtime::spawn_blocking(signature_infos)
+        .await
+        .map_err(|e| AgentError::SpawnFailed(e.to_string()))
+}
+
+fn signature_infos() -> Vec<SignatureInfo> {
+    let package = |name: &str| (!name.is_empty()).then(|| name.to_string());
+    agent_signatures()
+        .into_iter()
+        .map(|sig| SignatureInfo {
+            installed: sig.names().any(|name| find_on_path(name).is_some()),
+            command: sig.command.to_string(),
+            aliases: sig.aliases.iter().map(|alias| alias.to_string()).collect(),
+            name: sig.name.to_string(),
+            short_name: sig.short_name.to_string(),
+            color: sig.color.to_string(),
+            npm_package: package(sig.npm_package),
+            brew_formula: package(sig.brew_formula),
+            cargo_crate: package(sig.cargo_crate),
+            pip_package: package(sig.pip_package),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentAvailability {
+    pub available: bool,
+    pub path: Option<String>,
+}
+
+/// Cheap PATH lookup for a single command, without running `--version`.
+/// It still stats every PATH entry, so it runs on a blocking thread.
+#[tauri::command]
+async fn is_agent_available(command: String) -> Result<AgentAvailability, AgentError> {
+    let path = tauri::async_runtime::spawn_blocking(move || find_on_path(&command))
+        .await
+        .map_err(|e| AgentError::SpawnFailed(e.to_string()))?;
+    Ok(AgentAvailability {
+        available: path.is_some(),
+        path,
+    })
+}
+
+/// Upgrade a discovered agent through the package manager it was installed
+/// with, streaming the output under the id `__update-<command>`. Returns the
+/// upgrade's exit code.
+#[tauri::command]
+async fn update_agent(
+    command: String,
+    state: State<'_, AgentState>,
+    app: AppHandle,
+) -> Result<Option<i32>, AgentError> {
+    let (id, source) = state
+        .lock_discovered()
+        .iter()
+        .find(|agent| agent.command == command || agent.id == command)
+        .map(|agent| (agent.id.clone(), agent.source))
+        .ok_or_else(|| AgentError::NotFound(command.clone()))?;
+    let sig = agent_signatures()
+        .into_iter()
+        .find(|sig| sig.command == id)
+        .ok_or_else(|| AgentError::NotFound(command.clone()))?;
+
+    let unsupported = || AgentError::UpdateUnsupported(command.clone());
+    let (mut cmd, args) = match source {
+        DiscoverySource::Npm => (npm_command(), vec!["update".to_string(), "-g".to_string(), sig.npm_package.to_string()]),
+        DiscoverySource::Brew => (Command::new("brew"), vec!["upgrade".to_string(), sig.brew_formula.to_string()]),
+        DiscoverySource::Cargo => {
+            // The crate may have any name when it was matched by binary
+            let krate = scan_cargo()
+                .unwrap_or_default()
+                .into_iter()
+                .find(|(_, command)| *command == sig.command)
+                .map(|(krate, _)| krate)
+                .ok_or_else(unsupported)?;
+            (Command::new("cargo"), vec!["install".to_string(), krate])
+        }
+        DiscoverySource::Pip => {
+            let pip = ["pip", "pip3"]
+                .into_iter()
+                .find(|pip| find_on_path(pip).is_some())
+                .ok_or_else(unsupported)?;
+            (Command::new(pip), vec!["install".to_string(), "-U".to_string(), sig.pip_package.to_string()])
+        }
+        DiscoverySource::Path => return Err(unsupported()),
+    };
+
+    cmd.args(args);
+
+    let id = update_id(sig.command);
+    validate_id(&id)?;
+    let run = RunOptions::default().prepare()?;
+    state.claim(&id)?;
+    let code = tauri::async_runtime::spawn_blocking({
+        let id = id.clone();
+        move || run_to_completion(&app, &id, cmd, run)
+    })
+    .await
+    .unwrap_or(None);
+    state.release(&id);
+
+    Ok(code)
+}
+
+/// Run id of an agent's upgrade. It is built from the signature's command,
+/// not the caller's input, so it only holds characters `validate_id` allows.
+fn update_id(command: &str) -> String {
+    format!("__update-{}", command)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentUpdateInfo {
+    pub command: String,
+    pub installed: String,
+    /// `None` if the registry couldn't be asked in time.
+    pub latest: Option<String>,
+    pub update_available: bool,
+}
+
+/// How long a single `npm view` query may take.
+const UPDATE_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// First `major.minor.patch` in `text`, e.g. from "1.0.3 (Claude Code)".
+fn parse_semver(text: &str) -> Option<(u64, u64, u64)> {
+    let caps = Regex::new(r"(\d+)\.(\d+)\.(\d+)").ok()?.captures(text)?;
+    let part = |i: usize| caps[i].parse().ok();
+    Some((part(1)?, part(2)?, part(3)?))
+}
+
+/// Ask npm for the latest published version of each npm-installed agent
+/// from the last scan. Queries run concurrently.
+#[tauri::command]
+async fn check_agent_updates(state: State<'_, AgentState>) -> Result<Vec<AgentUpdateInfo>, AgentError> {
+    let signatures = agent_signatures();
+    let agents: Vec<(String, String, &'static str)> = state
+        .lock_discovered()
+        .iter()
+        .filter(|agent| agent.source == DiscoverySource::Npm)
+        .filter_map(|agent| {
+            let sig = signatures.iter().find(|sig| sig.command == agent.id)?;
+            Some((agent.command.clone(), agent.version.clone(), sig.npm_package))
+        })
+        .collect();
+
+    let checks = tauri::async_runtime::spawn_blocking(move || {
+        let handles: Vec<_> = agents
+            .into_iter()
+            .map(|(command, installed, package)| {
+                std::thread::spawn(move || {
+                    let mut query = npm_command();
+                    query.args(["view", package, "version"]);
+                    let latest = parse_semver(&probe_first_line(query, UPDATE_CHECK_TIMEOUT).unwrap_or_default());
+                    let update_available = matches!(
+                        (parse_semver(&installed), latest),
+                        (Some(installed), Some(latest)) if latest > installed
+                    );
+                    AgentUpdateInfo {
+                        command,
+                        installed,
+                        latest: latest.map(|(major, minor, patch)| format!("{}.{}.{}", major, minor, patch)),
+                        update_available,
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().filter_map(|handle| handle.join().ok()).collect()
+    })
+    .await
+    .unwrap_or_default();
+
+    Ok(checks)
+}
+
+// ---------------------------------------------------------------------------
+// Heartbeat
+// ---------------------------------------------------------------------------
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatEvent {
+    /// Milliseconds since the Unix epoch.
+    pub ts: u64,
+    /// Number of busy agents, or `None` if the state lock was held, which
+    /// may mean the backend is stuck.
+    pub agent_count: Option<usize>,
+}
+
+/// Emit `agenthub-heartbeat` every few seconds so the frontend can tell a
+/// frozen backend from a quiet one. Never blocks on the state lock.
+fn start_heartbeat(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        let agent_count = match app.state::<AgentState>().busy.try_lock() {
+            Ok(busy) => Some(busy.len()),
+            Err(std::sync::TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner().len()),
+            Err(std::sync::TryLockError::WouldBlock) => None,
+        };
+        let ts = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let _ = app.emit("agenthub-heartbeat", &HeartbeatEvent { ts, agent_count });
+        std::thread::sleep(HEARTBEAT_INTERVAL);
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Logging
+// ---------------------------------------------------------------------------
+
+/// Handle for changing the log level at runtime via `set_log_level`.
+pub struct LogControl(reload::Handle<LevelFilter, Registry>);
+
+/// Log to stderr at `warn` until the user asks for more.
+fn init_logging() -> LogControl {
+    let (filter, handle) = reload::Layer::new(LevelFilter::WARN);
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .try_init();
+    LogControl(handle)
+}
+
+/// Change log verbosity: `off`, `error`, `warn`, `info`, `debug` or `trace`.
+/// `trace` logs every output line.
+#[tauri::command]
+fn set_log_level(level: String, log: State<'_, LogControl>) -> Result<(), AgentError> {
+    let filter: LevelFilter = level
+        .trim()
+        .parse()
+        .map_err(|_| AgentError::InvalidInput(format!("úroveň logování '{}'", level)))?;
+    log.0
+        .reload(filter)
+        .map_err(|e| AgentError::InvalidInput(e.to_string()))?;
+    tracing::info!(%filter, "log level changed");
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .manage(AgentState::default())
+        .manage(init_logging())
+        .setup(|app| {
+            start_heartbeat(app.handle().clone());
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            run_agent,
+            run_agent_shell,
+            run_agent_batch,
+            run_agent_dry_run,
+            is_agent_busy,
+            send_signal,
+            get_capabilities,
+            get_total_resources,
+            get_agent_metrics,
+            get_reader_status,
+            get_agent_binary,
+            #[cfg(any(debug_assertions, feature = "mock"))]
+            spawn_mock_agent,
+            subscribe_agent_output,
+            unsubscribe_agent_output,
+            discover_agents,
+            discover_agents_verbose,
+            probe_signature,
+            is_agent_available,
+            list_signatures,
+            update_agent,
+            check_agent_updates,
+            set_log_level,
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poisoned_lock_is_recovered() {
+        let state = AgentState::default();
+        state.lock_busy().insert("a".to_string());
+        let poisoned = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = state.busy.lock().unwrap();
+            panic!("poison the busy lock");
+        }));
+        assert!(poisoned.is_err());
+        assert!(state.busy.is_poisoned());
+        assert!(state.lock_busy().contains("a"));
+    }
+
+    #[test]
+    fn unsafe_ids_are_rejected() {
+        for id in ["", "   ", "a/b", "..", ".x"] {
+            assert!(matches!(validate_id(id), Err(AgentError::InvalidInput(_))), "{:?}", id);
+        }
+        assert!(validate_id("claude-1.run_2").is_ok());
+    }
+
+    #[test]
+    fn empty_commands_are_rejected() {
+        for command in ["", "   "] {
+            assert!(matches!(agent_command(command, "hi", false), Err(AgentError::InvalidInput(_))));
+        }
+    }
+
+    #[test]
+    fn absolute_paths_are_spawned_directly() {
+        #[cfg(windows)]
+        let path = r"C:\Tools\claude.exe";
+        #[cfg(not(windows))]
+        let path = "/usr/local/bin/claude";
+        let cmd = agent_command(path, "hi", false).unwrap();
+        assert_eq!(cmd.get_program(), path);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn bare_names_go_through_cmd() {
+        let cmd = agent_command("claude", "hi", false).unwrap();
+        assert_eq!(cmd.get_program(), "cmd");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn spawns_paths_with_spaces() {
+        let dir = std::env::temp_dir().join("agent hub test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("echo args.bat");
+        std::fs::write(&script, "@echo %*\r\n").unwrap();
+        let output = agent_command(script.to_str().unwrap(), "hi", false).unwrap().output().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("-p hi"));
+    }
+
+    /// Write an executable shell script to a fresh temp path.
+    #[cfg(unix)]
+    fn temp_script(name: &str, body: &str) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("agenthub-{}-{}", std::process::id(), name));
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hanging_version_probe_stops_at_the_deadline() {
+        let script = temp_script("hang", "exec sleep 60");
+        let started = Instant::now();
+        let version = get_version(script.to_str().unwrap(), "--version", Duration::from_millis(300));
+        let _ = std::fs::remove_file(&script);
+        assert_eq!(version, None);
+        // One timed-out flag, not one timeout per fallback
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn silent_version_flag_falls_back() {
+        let script = temp_script("fallback", r#"[ "$1" = "-v" ] && echo 1.2.3"#);
+        let version = get_version(script.to_str().unwrap(), "--version", Duration::from_secs(5));
+        let _ = std::fs::remove_file(&script);
+        assert_eq!(version, Some(("-v", "1.2.3".to_string())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_reaches_grandchildren() {
+        use std::os::unix::process::CommandExt;
+        // The backgrounded sleep holds stdout open after sh itself dies
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 60 & wait"]).stdout(Stdio::piped()).process_group(0);
+        let mut child = cmd.spawn().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let control = RunControl::new(child);
+        std::thread::sleep(Duration::from_millis(100));
+        let started = Instant::now();
+        control.kill();
+        let mut rest = Vec::new();
+        stdout.read_to_end(&mut rest).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(control.wait().is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_against_the_child_path() {
+        let script = temp_script("prepended", "exit 0");
+        let name = script.file_name().unwrap().to_str().unwrap();
+        let mut cmd = Command::new(name);
+        cmd.env("PATH", script.parent().unwrap());
+        let resolved = resolved_program(&cmd);
+        let _ = std::fs::remove_file(&script);
+        assert_eq!(resolved.as_deref(), script.to_str());
+    }
+
+    #[test]
+    fn aliases_map_to_their_signature() {
+        assert_eq!(signature_id(&Command::new("/usr/local/bin/claude-code")).as_deref(), Some("claude"));
+        assert_eq!(signature_id(&Command::new("cursor-agent")).as_deref(), Some("cursor"));
+    }
+
+    #[test]
+    fn cargo_installs_match_by_binary() {
+        let list = "ripgrep v14.1.0:\n    rg\nagent-cli v0.3.0 (https://example.com/agent#abc):\n    claude-code\n";
+        assert_eq!(match_cargo_installs(list), [("agent-cli".to_string(), "claude".to_string())]);
+    }
+
+    #[test]
+    fn pip_packages_match_normalized_names() {
+        let list = r#"[{"name": "requests", "version": "2.32.0"}, {"name": "Aider_Chat", "version": "0.86.1"}]"#;
+        assert_eq!(match_pip_packages(list), [("aider-chat".to_string(), "aider".to_string())]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn max_runtime_kill_is_not_a_success() {
+        use std::os::unix::process::CommandExt;
+        // Exits 0 on SIGTERM, so the exit code alone would look clean
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "trap 'exit 0' TERM; sleep 60 & wait"]).process_group(0);
+        let control = RunControl::new(cmd.spawn().unwrap());
+        std::thread::sleep(Duration::from_millis(100));
+        control.mark_stopped(StopReason::MaxRuntime);
+        control.terminate(Duration::from_millis(500));
+        let status = control.wait().unwrap();
+        let stop_reason = *lock_recover(&control.stop_reason, "stop reason");
+        assert!(!run_succeeded(status.code(), is_interrupt(status), stop_reason, &[]));
+        assert!(run_succeeded(Some(0), false, None, &[]));
+        assert!(run_succeeded(None, false, Some(StopReason::Manual), &[]));
+    }
+
+    #[test]
+    fn update_ids_are_valid_run_ids() {
+        for sig in agent_signatures() {
+            assert!(validate_id(&update_id(sig.command)).is_ok(), "{}", sig.command);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn login_shell_runs_report_the_agent() {
+        let cmd = agent_command("claude-code", "hi", true).unwrap();
+        assert_eq!(launched_program(&cmd).as_deref(), Some("claude-code"));
+        assert_eq!(signature_id(&cmd).as_deref(), Some("claude"));
+        let line = format!("exec {} {}", shell_quote("/opt/it's/agent"), shell_quote("-p"));
+        assert_eq!(login_shell_program(&line).as_deref(), Some("/opt/it's/agent"));
+    }
+
+    #[test]
+    fn bad_hooks_are_rejected_up_front() {
+        let hook = |script: &str, options: RunOptions| RunSpec {
+            id: "hook".to_string(),
+            command: String::new(),
+            message: String::new(),
+            script: Some(script.to_string()),
+            options: Some(options),
+        };
+        assert!(hook("echo done", RunOptions::default()).validate().is_ok());
+        assert!(hook("  ", RunOptions::default()).validate().is_err());
+        let missing_stdin = RunOptions {
+            stdin_file: Some("/nonexistent/agenthub-stdin".to_string()),
+            ..RunOptions::default()
+        };
+        assert!(hook("echo done", missing_stdin).validate().is_err());
+    }
+
+    #[test]
+    fn stderr_keeps_only_the_tail() {
+        let mut tail = StderrTail::new(10);
+        for line in ["first", "second", "third", "last"] {
+            tail.push(line.to_string());
+        }
+        assert_eq!(tail.finish(), ("third\nlast".to_string(), true));
+        let mut tail = StderrTail::new(10);
+        tail.push("short".to_string());
+        assert_eq!(tail.finish(), ("short".to_string(), false));
+    }
+
+    fn chunks(input: &[u8], max_line: usize) -> Vec<(String, bool, bool)> {
+        output_chunks(BufReader::new(input), max_line, encoding_rs::UTF_8)
+            .map(|c| (c.text, c.partial, c.truncated))
+            .collect()
+    }
+
+    fn texts(input: &[u8]) -> Vec<String> {
+        chunks(input, DEFAULT_MAX_LINE_BYTES).into_iter().map(|c| c.0).collect()
+    }
+
+    #[test]
+    fn invalid_utf8_does_not_end_the_stream() {
+        assert_eq!(texts(b"bad \xff\xfe line\nstill here\n"), ["bad \u{fffd}\u{fffd} line", "still here"]);
+    }
+
+    /// Hands out one piece per `read` call, like a pipe delivering output
+    /// in separate writes.
+    struct Pieces(Vec<&'static [u8]>);
+
+    impl IoRead for Pieces {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let piece = self.0.remove(0);
+            buf[..piece.len()].copy_from_slice(piece);
+            Ok(piece.len())
+        }
+    }
+
+    #[test]
+    fn carriage_returns_give_partial_chunks() {
+        let progress = chunks(b"10%\r50%\r100%\n", DEFAULT_MAX_LINE_BYTES);
+        assert_eq!(progress, [
+            ("10%".to_string(), true, false),
+            ("50%".to_string(), true, false),
+            ("100%".to_string(), false, false),
+        ]);
+    }
+
+    #[test]
+    fn crlf_split_across_reads_is_one_line() {
+        let reader = BufReader::new(Pieces(vec![b"done\r", b"\nnext\n"]));
+        let split: Vec<_> = output_chunks(reader, DEFAULT_MAX_LINE_BYTES, encoding_rs::UTF_8)
+            .map(|c| (c.text, c.partial))
+            .collect();
+        assert_eq!(split, [
+            ("done".to_string(), true),
+            ("done".to_string(), false),
+            ("next".to_string(), false),
+        ]);
+    }
+
+    #[test]
+    fn over_long_line_is_cut_and_resyncs() {
+        let mut input = vec![b'x'; 100];
+        input.extend_from_slice(b"\nnext\n");
+        assert_eq!(chunks(&input, 16), [
+            ("x".repeat(16), false, true),
+            ("next".to_string(), false, false),
+        ]);
+    }
+
+    #[test]
+    fn strip_ansi_removes_colors() {
+        let colored = BufReader::new(&b"\x1b[0;32mok\x1b[0m\n"[..]);
+        let lines: Vec<_> = read_output(colored, true, DEFAULT_MAX_LINE_BYTES, encoding_rs::UTF_8)
+            .map(|c| c.text)
+            .collect();
+        assert_eq!(lines, ["ok"]);
+    }
+
+    #[test]
+    fn decodes_configured_encoding() {
+        let options = RunOptions {
+            encoding: Some("windows-1250".to_string()),
+            ..RunOptions::default()
+        };
+        let bytes = b"\x9e\x6c\x75\x9d\x6f\x75\xe8\x6b\xfd\n";
+        let lines: Vec<_> = output_chunks(BufReader::new(&bytes[..]), DEFAULT_MAX_LINE_BYTES, options.encoding())
+            .map(|c| c.text)
+            .collect();
+        assert_eq!(lines, ["žluťoučký"]);
+    }
 }
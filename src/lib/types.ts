@@ -17,4 +17,20 @@ export interface Message {
   timestamp: number;
   tokens?: number;
   files?: number;
+  /** In-progress `\r` output that the next agent line overwrites. */
+  partial?: boolean;
+}
+
+export interface AgentError {
+  kind:
+    | 'not_found'
+    | 'already_running'
+    | 'spawn_failed'
+    | 'update_unsupported'
+    | 'invalid_pattern'
+    | 'io_error'
+    | 'unsupported_signal'
+    | 'signal_failed'
+    | 'invalid_input';
+  message: string;
 }